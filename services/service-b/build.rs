@@ -1,10 +1,50 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let protos = ["../../proto/services.proto", "../../proto/common.proto"];
     tonic_build::configure()
         .build_server(true)
         .build_client(true)
-        .compile(
-            &["../../proto/services.proto", "../../proto/common.proto"],
-            &["../../proto"],
-        )?;
+        .file_descriptor_set_path(out_dir.join("grpcarch_descriptor.bin"))
+        .compile_protos(&protos, &["../../proto"])?;
+
+    // Proto version: FNV-1a of the .proto sources, so it depends only on the
+    // schema and not on protoc or codegen versions, and any service in the repo
+    // can compute the same value
+    let mut proto_hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for proto in protos {
+        for byte in std::fs::read(proto)? {
+            proto_hash = (proto_hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    println!("cargo:rustc-env=GRPCARCH_PROTO_VERSION={:016x}", proto_hash);
+
+    // Build metadata for the service_b_build_info metric. GIT_COMMIT can be
+    // passed in where there is no checkout (e.g. the Docker build).
+    let git_commit = env::var("GIT_COMMIT")
+        .ok()
+        .or_else(|| command_output("git", &["rev-parse", "--short", "HEAD"]))
+        .unwrap_or_else(|| "unknown".into());
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let rust_version = command_output(&rustc, &["--version"])
+        .and_then(|version| version.split_whitespace().nth(1).map(String::from))
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=SERVICE_B_GIT_COMMIT={}", git_commit);
+    println!("cargo:rustc-env=SERVICE_B_RUST_VERSION={}", rust_version);
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
     Ok(())
 }
+
+/// Trimmed stdout of a successful command
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|s| !s.is_empty())
+}
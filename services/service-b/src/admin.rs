@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::recent::RecentRequests;
+use crate::telemetry::TelemetryProviders;
+
+/// Shared state for the admin HTTP endpoints
+#[derive(Clone)]
+pub struct AdminState {
+    pub telemetry: TelemetryProviders,
+    /// Mirrors the `grpcarch.ServiceB` gRPC health status
+    pub ready: Arc<AtomicBool>,
+    /// Set when recent requests are being tracked (`RECENT_REQUESTS`)
+    pub recent_requests: Option<Arc<RecentRequests>>,
+}
+
+/// Operational HTTP endpoints, served on `ADMIN_PORT` separately from gRPC:
+///
+/// - `GET /livez` is 200 whenever the process is up to answer it
+/// - `GET /readyz` is 200 once both downstreams are reachable and 503 before
+///   that or while shutting down, matching the gRPC readiness status
+/// - `GET /log-level` returns the active filter directives
+/// - `PUT /log-level` replaces them with the request body (e.g. `debug,h2=info`)
+/// - `POST /flush` exports buffered spans, metrics and logs immediately
+/// - `GET /recent` lists the most recent requests with their trace ids, newest
+///   first; `?request_id=...` narrows it to one request. Only routed when
+///   recent requests are tracked.
+pub fn router(state: AdminState) -> Router {
+    let mut router = Router::new()
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/log-level", get(get_log_level).put(set_log_level))
+        .route("/flush", post(flush_telemetry));
+    if state.recent_requests.is_some() {
+        router = router.route("/recent", get(recent_requests));
+    }
+    router.with_state(state)
+}
+
+pub async fn serve(port: u16, state: AdminState) {
+    let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[Service B] Failed to bind admin port {}: {}", port, e);
+            return;
+        }
+    };
+    println!("[Service B] Admin endpoints available on port {}", port);
+    if let Err(e) = axum::serve(listener, router(state)).await {
+        eprintln!("[Service B] Admin server failed: {}", e);
+    }
+}
+
+async fn livez() -> (StatusCode, &'static str) {
+    (StatusCode::OK, "ok")
+}
+
+async fn readyz(State(state): State<AdminState>) -> (StatusCode, &'static str) {
+    if state.ready.load(Ordering::Relaxed) {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    }
+}
+
+async fn get_log_level(State(state): State<AdminState>) -> (StatusCode, String) {
+    match state
+        .telemetry
+        .log_filter
+        .with_current(|filter| filter.to_string())
+    {
+        Ok(filter) => (StatusCode::OK, filter),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Invalid directives are rejected and leave the current filter untouched
+async fn set_log_level(State(state): State<AdminState>, body: String) -> (StatusCode, String) {
+    let directives = body.trim();
+    let filter = match EnvFilter::try_new(directives) {
+        Ok(filter) => filter,
+        Err(e) => {
+            warn!("[Service B] Rejected log filter {:?}: {}", directives, e);
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid filter directive {:?}: {}", directives, e),
+            );
+        }
+    };
+
+    match state.telemetry.log_filter.reload(filter) {
+        Ok(()) => {
+            info!("[Service B] Log filter set to {:?}", directives);
+            (StatusCode::OK, directives.to_string())
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn recent_requests(
+    State(state): State<AdminState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<serde_json::Value> {
+    let request_id = params.get("request_id").map(String::as_str);
+    Json(
+        state
+            .recent_requests
+            .map(|recent| recent.to_json(request_id))
+            .unwrap_or_default(),
+    )
+}
+
+/// The SDK flushes synchronously, so it runs off the async worker threads
+async fn flush_telemetry(State(state): State<AdminState>) -> (StatusCode, String) {
+    let errors = match tokio::task::spawn_blocking(move || state.telemetry.force_flush()).await {
+        Ok(errors) => errors,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    if errors.is_empty() {
+        info!("[Service B] Telemetry flushed");
+        (StatusCode::OK, String::from("flushed"))
+    } else {
+        warn!("[Service B] Telemetry flush failed: {}", errors.join("; "));
+        (StatusCode::INTERNAL_SERVER_ERROR, errors.join("\n"))
+    }
+}
@@ -0,0 +1,149 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::config::Secret;
+
+const AUTHORIZATION_HEADER: &str = "authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Accepted API keys from `API_KEYS` (comma-separated). Returns `None`, leaving
+/// the service unauthenticated, when the variable is unset; setting it without
+/// any keys is a configuration error.
+pub fn load_api_keys() -> Result<Option<Vec<Secret<String>>>, Box<dyn Error>> {
+    let Ok(raw) = env::var("API_KEYS") else {
+        return Ok(None);
+    };
+    let keys: Vec<Secret<String>> = raw
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| Secret::new(key.to_string()))
+        .collect();
+    if keys.is_empty() {
+        return Err("API_KEYS is set but contains no keys".into());
+    }
+    Ok(Some(keys))
+}
+
+/// Requires `authorization: Bearer <key>` with one of the configured keys.
+/// With no keys configured every request is let through.
+#[derive(Clone)]
+pub struct ApiKeyAuth {
+    keys: Option<Arc<[Vec<u8>]>>,
+}
+
+impl ApiKeyAuth {
+    pub fn new(keys: Option<&[Secret<String>]>) -> Self {
+        Self {
+            keys: keys.map(|keys| {
+                keys.iter()
+                    .map(|key| key.expose().as_bytes().to_vec())
+                    .collect()
+            }),
+        }
+    }
+}
+
+impl Interceptor for ApiKeyAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(keys) = &self.keys else {
+            return Ok(request);
+        };
+        let presented = request
+            .metadata()
+            .get(AUTHORIZATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix(BEARER_PREFIX))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+        if matches_any(keys, presented.as_bytes()) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("invalid API key"))
+        }
+    }
+}
+
+/// Checks every key, so the time taken doesn't reveal which one (if any)
+/// the presented key matched
+fn matches_any(keys: &[Vec<u8>], presented: &[u8]) -> bool {
+    keys.iter()
+        .fold(false, |valid, key| valid | constant_time_eq(key, presented))
+}
+
+/// Compares in time that depends only on the lengths of the inputs
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+
+    use super::*;
+
+    fn auth() -> ApiKeyAuth {
+        let keys = [
+            Secret::new(String::from("key-one")),
+            Secret::new(String::from("key-two")),
+        ];
+        ApiKeyAuth::new(Some(&keys))
+    }
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(value) = authorization {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION_HEADER, value.parse().unwrap());
+        }
+        request
+    }
+
+    fn rejection(authorization: Option<&str>) -> String {
+        let status = auth().call(request(authorization)).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        status.message().to_string()
+    }
+
+    #[test]
+    fn accepts_any_configured_key() {
+        assert!(auth().call(request(Some("Bearer key-one"))).is_ok());
+        assert!(auth().call(request(Some("Bearer key-two"))).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_missing_bearer_token() {
+        assert_eq!(rejection(None), "missing bearer token");
+        assert_eq!(rejection(Some("key-one")), "missing bearer token");
+        assert_eq!(rejection(Some("Basic key-one")), "missing bearer token");
+    }
+
+    #[test]
+    fn rejects_a_wrong_key() {
+        assert_eq!(rejection(Some("Bearer key-three")), "invalid API key");
+        assert_eq!(rejection(Some("Bearer key")), "invalid API key");
+        assert_eq!(rejection(Some("Bearer ")), "invalid API key");
+    }
+
+    #[test]
+    fn lets_everything_through_without_keys() {
+        assert!(ApiKeyAuth::new(None).call(request(None)).is_ok());
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_inputs() {
+        assert!(constant_time_eq(b"key-one", b"key-one"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"key-one", b"key-two"));
+        assert!(!constant_time_eq(b"key", b"key-one"));
+        assert!(!constant_time_eq(b"key-one", b"key"));
+        assert!(!constant_time_eq(b"key-one", b""));
+    }
+}
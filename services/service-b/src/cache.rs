@@ -0,0 +1,85 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use prost::Message;
+
+use crate::grpcarch::{ProcessRequest, ProcessResponse};
+
+/// In-memory LRU of successful `ProcessData` responses with a fixed TTL.
+///
+/// A hit returns the stored response without calling Service D or E, so this is
+/// only safe when processing is idempotent: a repeated request must be allowed
+/// to observe the earlier result instead of having its side effects applied
+/// again. Leave it disabled for any operation where that doesn't hold.
+pub struct ResponseCache {
+    entries: Mutex<LruCache<u64, CachedResponse>>,
+    ttl: Duration,
+}
+
+struct CachedResponse {
+    stored_at: Instant,
+    response: ProcessResponse,
+}
+
+impl ResponseCache {
+    pub fn new(max_entries: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(max_entries)),
+            ttl,
+        }
+    }
+
+    /// The unexpired response stored under `key`, dropping it if it has expired
+    pub fn get(&self, key: u64) -> Option<ProcessResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => Some(entry.response.clone()),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: u64, response: ProcessResponse) {
+        self.entries.lock().unwrap().put(
+            key,
+            CachedResponse {
+                stored_at: Instant::now(),
+                response,
+            },
+        );
+    }
+}
+
+/// Hash of everything in the request that affects the response. The metadata
+/// (request id, timestamps) is left out so retries of the same work share an entry.
+pub fn cache_key(req: &ProcessRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(payload) = &req.payload {
+        payload.id.hash(&mut hasher);
+        payload.content.hash(&mut hasher);
+        let mut attributes: Vec<_> = payload.attributes.iter().collect();
+        attributes.sort();
+        attributes.hash(&mut hasher);
+        // Typed values hold doubles, so they are hashed by their encoding
+        let mut typed_attributes: Vec<_> = payload
+            .typed_attributes
+            .iter()
+            .map(|(key, value)| (key, value.encode_to_vec()))
+            .collect();
+        typed_attributes.sort();
+        typed_attributes.hash(&mut hasher);
+    }
+    req.operation.hash(&mut hasher);
+    for value in &req.input_values {
+        value.to_bits().hash(&mut hasher);
+    }
+    req.validation_rules.hash(&mut hasher);
+    hasher.finish()
+}
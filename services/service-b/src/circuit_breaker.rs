@@ -0,0 +1,108 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Settings shared by every downstream breaker
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures that trip the breaker open
+    pub failure_threshold: u32,
+    /// How long the breaker stays open before allowing a probe
+    pub cooldown: Duration,
+}
+
+/// Externally visible breaker state, exported as the `service_b_circuit_state` gauge
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed = 0,
+    HalfOpen = 1,
+    Open = 2,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Closed { consecutive_failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probe_started: Instant },
+}
+
+/// Consecutive-failure circuit breaker for a single downstream.
+///
+/// Closed: calls pass through; `failure_threshold` consecutive failures open it.
+/// Open: calls fail fast until `cooldown` has elapsed.
+/// Half-open: a single probe call is let through; its outcome closes or re-opens
+/// the breaker. A probe that never reports back (e.g. cancelled) is considered
+/// lost after another `cooldown`, and a new probe is allowed.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::Closed {
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    /// Returns whether a call may proceed, transitioning open -> half-open once
+    /// the cooldown has elapsed
+    pub fn try_acquire(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match *inner {
+            Inner::Closed { .. } => true,
+            Inner::Open { until } if now >= until => {
+                *inner = Inner::HalfOpen { probe_started: now };
+                true
+            }
+            Inner::Open { .. } => false,
+            Inner::HalfOpen { probe_started } if now >= probe_started + self.config.cooldown => {
+                *inner = Inner::HalfOpen { probe_started: now };
+                true
+            }
+            Inner::HalfOpen { .. } => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.inner.lock().unwrap() = Inner::Closed {
+            consecutive_failures: 0,
+        };
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let open = Inner::Open {
+            until: Instant::now() + self.config.cooldown,
+        };
+        *inner = match *inner {
+            Inner::Closed {
+                consecutive_failures,
+            } if consecutive_failures + 1 < self.config.failure_threshold => Inner::Closed {
+                consecutive_failures: consecutive_failures + 1,
+            },
+            _ => open,
+        };
+    }
+
+    /// Whether calls are being failed fast: open, with the cooldown still
+    /// running
+    pub fn is_open(&self) -> bool {
+        match *self.inner.lock().unwrap() {
+            Inner::Open { until } => Instant::now() < until,
+            _ => false,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.inner.lock().unwrap() {
+            Inner::Closed { .. } => CircuitState::Closed,
+            Inner::HalfOpen { .. } => CircuitState::HalfOpen,
+            Inner::Open { .. } => CircuitState::Open,
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{BoxError, Layer, Service};
+
+use crate::priority::Priority;
+use crate::ServiceBMetrics;
+
+/// Health probes must keep working while the server is saturated
+const EXEMPT_PATH_PREFIX: &str = "/grpc.health.v1.Health/";
+
+/// Lets requests over the concurrency limit wait briefly for a slot
+#[derive(Clone, Copy, Debug)]
+pub struct QueueConfig {
+    /// Requests allowed to wait at once; any more are rejected outright
+    pub max_depth: usize,
+    /// How long a queued request waits for a slot before it is rejected
+    pub timeout: Duration,
+}
+
+/// Caps the number of in-flight requests. Unlike tower's `ConcurrencyLimitLayer`,
+/// which queues excess requests without bound, this rejects them with
+/// `RESOURCE_EXHAUSTED` so callers get a clear overload signal. With a queue
+/// configured, up to `max_depth` of them first wait up to `timeout` for a slot,
+/// absorbing short bursts; low-priority requests (`x-priority: low`) may only
+/// fill part of the queue, so they are the first turned away.
+#[derive(Clone)]
+pub struct ConcurrencyLimitLayer {
+    semaphore: Arc<Semaphore>,
+    max_concurrent: usize,
+    queue: Option<QueueConfig>,
+    queued: Arc<AtomicUsize>,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(
+        max_concurrent: usize,
+        queue: Option<QueueConfig>,
+        metrics: Arc<ServiceBMetrics>,
+    ) -> Self {
+        metrics.record_concurrency_saturation(0.0);
+        if queue.is_some() {
+            metrics.record_queue_depth(0);
+        }
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            queue,
+            queued: Arc::new(AtomicUsize::new(0)),
+            metrics,
+        }
+    }
+
+    /// Waits for a slot if the queue has room for this priority, recording how
+    /// long it took. `None` when the queue is full, disabled, or the wait timed
+    /// out.
+    async fn wait_for_slot(&self, priority: Priority) -> Option<OwnedSemaphorePermit> {
+        let queue = self.queue?;
+        let depth = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        let _queued = QueuedGuard(self.clone());
+        if depth as f64 > queue.max_depth as f64 * priority.queue_share() {
+            return None;
+        }
+        self.metrics.record_queue_depth(depth);
+
+        let start = Instant::now();
+        let permit = tokio::time::timeout(queue.timeout, self.semaphore.clone().acquire_owned())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        self.metrics.record_queue_wait(
+            if permit.is_some() {
+                "acquired"
+            } else {
+                "timed_out"
+            },
+            start.elapsed().as_secs_f64() * 1000.0,
+        );
+        permit
+    }
+
+    /// `releasing` counts permits that are about to be returned but still held
+    fn record_saturation(&self, releasing: usize) {
+        let in_use = self.max_concurrent - self.semaphore.available_permits() - releasing;
+        self.metrics
+            .record_concurrency_saturation(in_use as f64 / self.max_concurrent as f64);
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConcurrencyLimit {
+            inner,
+            limit: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    limit: ConcurrencyLimitLayer,
+}
+
+/// Holds a slot for the lifetime of a request and updates the saturation gauge
+/// when it is released
+struct SlotGuard {
+    _permit: OwnedSemaphorePermit,
+    limit: ConcurrencyLimitLayer,
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        // The permit is only returned after this body runs
+        self.limit.record_saturation(1);
+    }
+}
+
+/// Counts a request as queued until it gets a slot, times out or is cancelled
+struct QueuedGuard(ConcurrencyLimitLayer);
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        let depth = self.0.queued.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.0.metrics.record_queue_depth(depth);
+    }
+}
+
+impl<S, B, ResBody> Service<http::Request<B>> for ConcurrencyLimit<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        if req.uri().path().starts_with(EXEMPT_PATH_PREFIX) {
+            return Box::pin(async move { inner.call(req).await.map_err(Into::into) });
+        }
+
+        let limit = self.limit.clone();
+        let priority = Priority::from_headers(req.headers());
+        Box::pin(async move {
+            let permit = match limit.semaphore.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => match limit.wait_for_slot(priority).await {
+                    Some(permit) => permit,
+                    None => {
+                        limit.metrics.record_rejected("concurrency_limit", priority);
+                        return Err(Box::new(Status::resource_exhausted(format!(
+                            "Service B is at its concurrency limit ({} in-flight requests)",
+                            limit.max_concurrent
+                        ))) as BoxError);
+                    }
+                },
+            };
+            limit.record_saturation(0);
+            let _guard = SlotGuard {
+                _permit: permit,
+                limit,
+            };
+            inner.call(req).await.map_err(Into::into)
+        })
+    }
+}
@@ -0,0 +1,635 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{ClientTlsConfig, ServerTlsConfig};
+use tower_http::cors::CorsLayer;
+
+use crate::circuit_breaker::CircuitBreakerConfig;
+use crate::concurrency::QueueConfig;
+use crate::failure_policy::{self, DownstreamPolicies};
+use crate::load_shed::LoadShedConfig;
+use crate::redact::PayloadRedactor;
+use crate::routing::{self, DownstreamRouting};
+use crate::telemetry::TelemetryConfig;
+use crate::{auth, grpc_web, tls, CanaryConfig, ChannelTuning, ResponseCacheConfig};
+
+/// A setting that is missing, malformed or out of range. The message names the
+/// variable and the value it was given.
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Just the message, since this is what `main` prints when startup fails
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<String> for ConfigError {
+    fn from(message: String) -> Self {
+        Self(message)
+    }
+}
+
+impl From<&str> for ConfigError {
+    fn from(message: &str) -> Self {
+        Self(message.to_string())
+    }
+}
+
+/// For the loaders that read files as well as variables (e.g. TLS), whose
+/// errors already describe the setting at fault
+impl From<Box<dyn Error>> for ConfigError {
+    fn from(error: Box<dyn Error>) -> Self {
+        Self(error.to_string())
+    }
+}
+
+/// A sensitive setting, such as an API key or private key material. `Debug`
+/// and `Display` print a placeholder, so logging a config can't leak it; the
+/// value is only reachable through `expose`.
+#[derive(Clone)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***redacted***")
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***redacted***")
+    }
+}
+
+/// Every setting Service B takes from the environment, read and validated once
+/// at startup so a bad value fails fast instead of surfacing mid-request
+#[derive(Debug)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub downstream: DownstreamConfig,
+    pub processing: ProcessingConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+/// How the gRPC server listens and what it lets through
+#[derive(Debug)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    /// Listen on this Unix socket instead of `addr` (sidecar deployments)
+    pub uds_path: Option<PathBuf>,
+    pub tls: Option<ServerTlsConfig>,
+    /// Set when gRPC-Web is enabled
+    pub grpc_web_cors: Option<CorsLayer>,
+    pub api_keys: Option<Vec<Secret<String>>>,
+    pub max_concurrent_streams: u32,
+    pub initial_stream_window_size: u32,
+    pub max_frame_size: u32,
+    pub max_message_bytes: usize,
+    /// Used for downstream calls as well
+    pub compression: Option<CompressionEncoding>,
+    pub enable_reflection: bool,
+    pub shutdown_grace: Duration,
+    /// Report NOT_SERVING while both downstream breakers are open
+    pub health_reflects_downstream: bool,
+    pub admin_port: Option<u16>,
+    /// Finished requests kept for the admin `/recent` endpoint; `None` without
+    /// an admin server
+    pub recent_requests: Option<NonZeroUsize>,
+    /// 0 leaves requests unbounded
+    pub max_concurrent_requests: usize,
+    pub queue: Option<QueueConfig>,
+    pub load_shed: Option<LoadShedConfig>,
+    pub handler_timeout: Option<Duration>,
+}
+
+/// Where Service D and E are and how they are called
+#[derive(Debug)]
+pub struct DownstreamConfig {
+    pub service_d_addr: String,
+    pub service_e_addr: String,
+    pub service_d_timeout: Duration,
+    pub service_e_timeout: Duration,
+    pub service_e_hedge_delay: Option<Duration>,
+    pub service_e_canary: Option<CanaryConfig>,
+    pub max_retries: u32,
+    pub retry_budget: Option<RetryBudgetConfig>,
+    pub breaker: CircuitBreakerConfig,
+    /// Secret since it carries the client's private key
+    pub tls: Option<Secret<ClientTlsConfig>>,
+    pub channel_tuning: ChannelTuning,
+    pub dns_refresh: Option<Duration>,
+    pub routing: DownstreamRouting,
+    pub failure_policies: DownstreamPolicies,
+    /// Connect to both downstreams at startup rather than on first use
+    pub warmup: bool,
+}
+
+/// Size of the shared retry budget, see `RetryBudget::new`
+#[derive(Clone, Copy, Debug)]
+pub struct RetryBudgetConfig {
+    pub max_tokens: f64,
+    pub token_ratio: f64,
+}
+
+/// How `ProcessData` handles, records and logs requests
+#[derive(Debug)]
+pub struct ProcessingConfig {
+    pub processor_id: String,
+    pub latency_ema_alpha: f64,
+    pub dry_run: bool,
+    pub slow_request_threshold: Option<Duration>,
+    pub rng_seed: Option<u64>,
+    pub response_cache: Option<ResponseCacheConfig>,
+    pub idempotency_store: Option<ResponseCacheConfig>,
+    pub payload_redactor: Option<PayloadRedactor>,
+    pub compute_fallback: bool,
+    pub compute_fallback_value: Option<f64>,
+    pub rate_limit_rps: Option<f64>,
+    pub rate_limit_labelled_callers: Vec<String>,
+    pub baggage_span_attributes: Vec<String>,
+    pub constant_labels: Vec<KeyValue>,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Ok(Self {
+            server: server_config()?,
+            downstream: downstream_config()?,
+            processing: processing_config()?,
+            telemetry: TelemetryConfig::from_env()?,
+        })
+    }
+}
+
+fn server_config() -> Result<ServerConfig, ConfigError> {
+    // Defaults to every interface; set to e.g. 127.0.0.1 or the pod IP to
+    // listen on just one
+    let bind_addr = env::var("GRPC_BIND_ADDR").unwrap_or_else(|_| "0.0.0.0".into());
+    let bind_ip: IpAddr = bind_addr
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid value for GRPC_BIND_ADDR: {:?} ({})", bind_addr, e))?;
+    let port = env::var("GRPC_PORT").unwrap_or_else(|_| "50052".into());
+    let port: u16 = port
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid value for GRPC_PORT: {:?} ({})", port, e))?;
+
+    // Per-connection HTTP/2 limits, so one client can't monopolise the server
+    let max_concurrent_streams = env_parse::<u32>("GRPC_MAX_CONCURRENT_STREAMS", 256)?;
+    let initial_stream_window_size =
+        env_parse::<u32>("GRPC_INITIAL_STREAM_WINDOW_SIZE", 1024 * 1024)?;
+    let max_frame_size = env_parse::<u32>("GRPC_MAX_FRAME_SIZE", 16 * 1024)?;
+    // The bounds HTTP/2 allows for these settings
+    if initial_stream_window_size > i32::MAX as u32 {
+        return Err(format!(
+            "Invalid value for GRPC_INITIAL_STREAM_WINDOW_SIZE: {} (must be at most {})",
+            initial_stream_window_size,
+            i32::MAX
+        )
+        .into());
+    }
+    if !(16_384..=16_777_215).contains(&max_frame_size) {
+        return Err(format!(
+            "Invalid value for GRPC_MAX_FRAME_SIZE: {} (must be 16384-16777215)",
+            max_frame_size
+        )
+        .into());
+    }
+
+    // tonic's own default: ample for normal payloads, while bounding the
+    // memory a single request can make the decoder allocate
+    let max_message_bytes = env_parse::<usize>("MAX_MESSAGE_BYTES", 4 * 1024 * 1024)?;
+    if max_message_bytes == 0 {
+        return Err("MAX_MESSAGE_BYTES must be greater than 0".into());
+    }
+
+    let max_concurrent_requests = env_parse::<usize>("MAX_CONCURRENT_REQUESTS", 0)?;
+    // 0 (the default) rejects requests over the concurrency limit immediately
+    let queue_timeout_ms = env_parse::<u64>("QUEUE_TIMEOUT_MS", 0)?;
+    let queue_max_depth = env_parse::<usize>("QUEUE_MAX_DEPTH", 100)?;
+    let queue = (queue_timeout_ms > 0 && queue_max_depth > 0).then(|| QueueConfig {
+        max_depth: queue_max_depth,
+        timeout: Duration::from_millis(queue_timeout_ms),
+    });
+
+    // 0 (the default) disables latency-based load shedding
+    let load_shed_target_ms = env_parse::<u64>("LOAD_SHED_TARGET_MS", 0)?;
+    let load_shed_max_rate = env_parse::<f64>("LOAD_SHED_MAX_RATE", 0.5)?;
+    if !(load_shed_max_rate > 0.0 && load_shed_max_rate <= 1.0) {
+        return Err(format!(
+            "LOAD_SHED_MAX_RATE must be in (0, 1], got {}",
+            load_shed_max_rate
+        )
+        .into());
+    }
+    let load_shed_window_ms = env_parse::<u64>("LOAD_SHED_WINDOW_MS", 1000)?;
+    if load_shed_window_ms == 0 {
+        return Err("LOAD_SHED_WINDOW_MS must be greater than 0".into());
+    }
+
+    // 0 (the default) disables the handler timeout
+    let handler_timeout_ms = env_parse::<u64>("HANDLER_TIMEOUT_MS", 0)?;
+    // 0 (the default) disables the admin HTTP server
+    let admin_port = env_parse::<u16>("ADMIN_PORT", 0)?;
+    // Only tracked when the admin server is there to serve them; 0 disables
+    let recent_requests =
+        NonZeroUsize::new(env_parse::<usize>("RECENT_REQUESTS", 100)?).filter(|_| admin_port > 0);
+
+    Ok(ServerConfig {
+        addr: SocketAddr::new(bind_ip, port),
+        uds_path: env::var("GRPC_UDS_PATH").ok().map(PathBuf::from),
+        tls: tls::load_server_tls()?,
+        grpc_web_cors: grpc_web::load_grpc_web_cors()?,
+        api_keys: auth::load_api_keys()?,
+        max_concurrent_streams,
+        initial_stream_window_size,
+        max_frame_size,
+        max_message_bytes,
+        // gzip trades CPU for bandwidth on large payloads; off by default
+        compression: env_parse::<bool>("GRPC_COMPRESSION", false)?
+            .then_some(CompressionEncoding::Gzip),
+        // Off by default so the schema isn't exposed publicly; docker-compose
+        // turns it on
+        enable_reflection: env_parse::<bool>("ENABLE_REFLECTION", false)?,
+        shutdown_grace: Duration::from_secs(env_parse("SHUTDOWN_GRACE_SECS", 10)?),
+        health_reflects_downstream: env_parse::<bool>("HEALTH_REFLECTS_DOWNSTREAM", false)?,
+        admin_port: (admin_port > 0).then_some(admin_port),
+        recent_requests,
+        max_concurrent_requests,
+        queue,
+        load_shed: (load_shed_target_ms > 0).then(|| LoadShedConfig {
+            target: Duration::from_millis(load_shed_target_ms),
+            max_shed_rate: load_shed_max_rate,
+            window: Duration::from_millis(load_shed_window_ms),
+        }),
+        handler_timeout: (handler_timeout_ms > 0)
+            .then(|| Duration::from_millis(handler_timeout_ms)),
+    })
+}
+
+fn downstream_config() -> Result<DownstreamConfig, ConfigError> {
+    // 0 (the default) disables hedging of Service E calls
+    let hedge_delay_ms = env_parse::<u64>("HEDGE_DELAY_MS", 0)?;
+    // 0 (the default) disables the retry budget
+    let retry_budget_ratio = env_parse::<f64>("RETRY_BUDGET_RATIO", 0.0)?;
+    let retry_budget_max_tokens = env_parse::<f64>("RETRY_BUDGET_MAX_TOKENS", 10.0)?;
+    if !(retry_budget_ratio >= 0.0 && retry_budget_ratio.is_finite()) {
+        return Err(format!(
+            "RETRY_BUDGET_RATIO must be a non-negative number, got {}",
+            retry_budget_ratio
+        )
+        .into());
+    }
+    if !(retry_budget_max_tokens > 0.0 && retry_budget_max_tokens.is_finite()) {
+        return Err(format!(
+            "RETRY_BUDGET_MAX_TOKENS must be greater than 0, got {}",
+            retry_budget_max_tokens
+        )
+        .into());
+    }
+
+    // 0 disables the respective keepalive
+    let keepalive_secs = env_parse::<u64>("DOWNSTREAM_KEEPALIVE_SECS", 30)?;
+    let tcp_keepalive_secs = env_parse::<u64>("DOWNSTREAM_TCP_KEEPALIVE_SECS", 30)?;
+    let channel_tuning = ChannelTuning {
+        connect_timeout: Duration::from_millis(env_parse("DOWNSTREAM_CONNECT_TIMEOUT_MS", 5000)?),
+        tcp_keepalive: (tcp_keepalive_secs > 0).then(|| Duration::from_secs(tcp_keepalive_secs)),
+        http2_keepalive_interval: (keepalive_secs > 0).then(|| Duration::from_secs(keepalive_secs)),
+        http2_keepalive_timeout: Duration::from_secs(env_parse(
+            "DOWNSTREAM_KEEPALIVE_TIMEOUT_SECS",
+            10,
+        )?),
+        keepalive_while_idle: env_parse("DOWNSTREAM_KEEPALIVE_WHILE_IDLE", true)?,
+    };
+    // 0 (the default) disables DNS re-resolution
+    let dns_refresh_secs = env_parse::<u64>("DNS_REFRESH_SECS", 0)?;
+
+    // Without a percentage every compute call is mirrored; 0 mirrors none
+    let service_e_canary = match env::var("SERVICE_E_CANARY_ADDR") {
+        Err(_) => None,
+        Ok(addr) => {
+            let mirror_percent = env_parse::<f64>("E_MIRROR_PERCENT", 100.0)?;
+            if !(0.0..=100.0).contains(&mirror_percent) {
+                return Err(format!(
+                    "E_MIRROR_PERCENT must be between 0 and 100, got {}",
+                    mirror_percent
+                )
+                .into());
+            }
+            (mirror_percent > 0.0).then_some(CanaryConfig {
+                addr,
+                mirror_percent,
+            })
+        }
+    };
+
+    Ok(DownstreamConfig {
+        service_d_addr: env::var("SERVICE_D_ADDR").unwrap_or_else(|_| "localhost:50054".into()),
+        service_e_addr: env::var("SERVICE_E_ADDR").unwrap_or_else(|_| "localhost:50055".into()),
+        service_d_timeout: Duration::from_millis(env_parse("SERVICE_D_TIMEOUT_MS", 500)?),
+        service_e_timeout: Duration::from_millis(env_parse("SERVICE_E_TIMEOUT_MS", 500)?),
+        service_e_hedge_delay: (hedge_delay_ms > 0).then(|| Duration::from_millis(hedge_delay_ms)),
+        service_e_canary,
+        max_retries: env_parse("DOWNSTREAM_MAX_RETRIES", 2)?,
+        retry_budget: (retry_budget_ratio > 0.0).then_some(RetryBudgetConfig {
+            max_tokens: retry_budget_max_tokens,
+            token_ratio: retry_budget_ratio,
+        }),
+        breaker: CircuitBreakerConfig {
+            failure_threshold: env_parse("CB_FAILURE_THRESHOLD", 5)?,
+            cooldown: Duration::from_millis(env_parse("CB_COOLDOWN_MS", 5000)?),
+        },
+        tls: tls::load_client_tls()?.map(Secret::new),
+        channel_tuning,
+        dns_refresh: (dns_refresh_secs > 0).then(|| Duration::from_secs(dns_refresh_secs)),
+        routing: routing::load_routing()?,
+        failure_policies: failure_policy::load_failure_policies()?,
+        // Off by default; saves the first requests after a scale-up the
+        // connection handshakes
+        warmup: env_parse::<bool>("WARMUP", false)?,
+    })
+}
+
+fn processing_config() -> Result<ProcessingConfig, ConfigError> {
+    let latency_ema_alpha = env_parse::<f64>("LATENCY_EMA_ALPHA", 0.1)?;
+    if !(latency_ema_alpha > 0.0 && latency_ema_alpha <= 1.0) {
+        return Err(format!(
+            "LATENCY_EMA_ALPHA must be in (0, 1], got {}",
+            latency_ema_alpha
+        )
+        .into());
+    }
+    // 0 (the default) disables slow-request warnings
+    let slow_request_ms = env_parse::<u64>("SLOW_REQUEST_MS", 0)?;
+    // 0 (the default) disables the response cache; only enable it when
+    // ProcessData is idempotent for the workload
+    let cache_ttl_secs = env_parse::<u64>("CACHE_TTL_SECS", 0)?;
+    let cache_max_entries = env_parse::<usize>("CACHE_MAX_ENTRIES", 1000)?;
+    // 0 (the default) ignores idempotency-key headers
+    let idempotency_ttl_secs = env_parse::<u64>("IDEMPOTENCY_TTL_SECS", 0)?;
+    let idempotency_max_entries = env_parse::<usize>("IDEMPOTENCY_MAX_ENTRIES", 1000)?;
+    // Off by default; attributes named in REDACT_KEYS are masked and content
+    // truncated before anything is logged
+    let log_payloads = env_parse::<bool>("LOG_PAYLOADS", false)?;
+    let log_payload_max_content = env_parse::<usize>("LOG_PAYLOAD_MAX_CONTENT", 256)?;
+    // 0 (the default) disables per-caller rate limiting
+    let rate_limit_rps = env_parse::<f64>("RATE_LIMIT_RPS", 0.0)?;
+
+    Ok(ProcessingConfig {
+        processor_id: env::var("PROCESSOR_ID").unwrap_or_else(|_| default_processor_id()),
+        latency_ema_alpha,
+        // Smoke-test mode: ProcessData answers without calling Service D or E
+        dry_run: env_parse::<bool>("DRY_RUN", false)?,
+        slow_request_threshold: (slow_request_ms > 0)
+            .then(|| Duration::from_millis(slow_request_ms)),
+        // Unset (the default) seeds the simulated delays from OS entropy
+        rng_seed: env_parse_optional::<u64>("RNG_SEED")?,
+        response_cache: cache_config(cache_ttl_secs, cache_max_entries),
+        idempotency_store: cache_config(idempotency_ttl_secs, idempotency_max_entries),
+        payload_redactor: log_payloads.then(|| {
+            PayloadRedactor::new(
+                comma_separated(&env::var("REDACT_KEYS").unwrap_or_default()),
+                log_payload_max_content,
+            )
+        }),
+        // Off by default. When on, failed compute calls are answered with the
+        // last good result for the same inputs, else COMPUTE_FALLBACK_VALUE if set
+        compute_fallback: env_parse::<bool>("ENABLE_COMPUTE_FALLBACK", false)?,
+        compute_fallback_value: env_parse_optional::<f64>("COMPUTE_FALLBACK_VALUE")?,
+        rate_limit_rps: (rate_limit_rps > 0.0).then_some(rate_limit_rps),
+        // caller-service values (e.g. service-a) that get their own `caller`
+        // label on rate-limit rejections; any other caller is counted as other
+        rate_limit_labelled_callers: comma_separated(
+            &env::var("RATE_LIMIT_LABELLED_CALLERS").unwrap_or_default(),
+        ),
+        // Baggage keys (e.g. tenant.id) to record on request spans. Baggage is
+        // caller-controlled, so only allowlisted keys become attributes.
+        baggage_span_attributes: comma_separated(
+            &env::var("BAGGAGE_SPAN_ATTRIBUTES").unwrap_or_default(),
+        ),
+        // e.g. region=us-east-1,cluster=a; saves relabelling in the collector
+        constant_labels: parse_constant_labels(
+            &env::var("METRIC_CONSTANT_LABELS").unwrap_or_default(),
+        )?,
+    })
+}
+
+/// Reads and parses an environment variable, falling back to `default` when unset
+fn env_parse<T>(name: &str, default: T) -> Result<T, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    Ok(env_parse_optional(name)?.unwrap_or(default))
+}
+
+/// Reads and parses an environment variable, `None` when unset
+fn env_parse_optional<T>(name: &str) -> Result<Option<T>, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("Invalid value for {}: {:?} ({})", name, value, e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The non-empty entries of a comma-separated list, trimmed
+fn comma_separated(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `None` when either the TTL or the size is 0
+fn cache_config(ttl_secs: u64, max_entries: usize) -> Option<ResponseCacheConfig> {
+    NonZeroUsize::new(max_entries)
+        .filter(|_| ttl_secs > 0)
+        .map(|max_entries| ResponseCacheConfig {
+            max_entries,
+            ttl: Duration::from_secs(ttl_secs),
+        })
+}
+
+/// Parses comma-separated `name=value` pairs into metric labels. Names must be
+/// valid Prometheus label names and may not repeat or shadow `method` and
+/// `status`; values may not be empty.
+fn parse_constant_labels(raw: &str) -> Result<Vec<KeyValue>, ConfigError> {
+    let mut labels: Vec<KeyValue> = Vec::new();
+    for pair in comma_separated(raw) {
+        let (name, value) = pair
+            .split_once('=')
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .ok_or_else(|| format!("METRIC_CONSTANT_LABELS entry {:?} is not name=value", pair))?;
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(format!("Invalid label name in METRIC_CONSTANT_LABELS: {:?}", name).into());
+        }
+        if name == "method" || name == "status" {
+            return Err(format!("METRIC_CONSTANT_LABELS may not set the {:?} label", name).into());
+        }
+        if labels.iter().any(|label| label.key.as_str() == name) {
+            return Err(format!("Duplicate label in METRIC_CONSTANT_LABELS: {:?}", name).into());
+        }
+        if value.is_empty() {
+            return Err(
+                format!("Empty value for label {:?} in METRIC_CONSTANT_LABELS", name).into(),
+            );
+        }
+        labels.push(KeyValue::new(name.to_string(), value.to_string()));
+    }
+    Ok(labels)
+}
+
+/// The hostname, so each replica reports a distinct processor id. Containers
+/// set `HOSTNAME`; elsewhere it is read from `/etc/hostname`.
+fn default_processor_id() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| String::from("service-b-processor"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test sets its own variables, since tests share the process
+    // environment and run in parallel
+
+    #[test]
+    fn env_parse_reports_a_malformed_value() {
+        env::set_var("CONFIG_TEST_MALFORMED_PORT", "50o52");
+        let error = env_parse::<u16>("CONFIG_TEST_MALFORMED_PORT", 50052).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for CONFIG_TEST_MALFORMED_PORT: \"50o52\" (invalid digit found in string)"
+        );
+    }
+
+    #[test]
+    fn env_parse_falls_back_to_the_default_when_unset() {
+        assert_eq!(env_parse::<u64>("CONFIG_TEST_UNSET", 7).unwrap(), 7);
+        assert_eq!(
+            env_parse_optional::<u64>("CONFIG_TEST_UNSET").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn env_parse_optional_reports_a_malformed_value() {
+        env::set_var("CONFIG_TEST_MALFORMED_FLAG", "yes");
+        let error = env_parse_optional::<bool>("CONFIG_TEST_MALFORMED_FLAG").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for CONFIG_TEST_MALFORMED_FLAG: \"yes\" (provided string was not `true` or `false`)"
+        );
+
+        env::set_var("CONFIG_TEST_SEED", "42");
+        assert_eq!(
+            env_parse_optional::<u64>("CONFIG_TEST_SEED").unwrap(),
+            Some(42)
+        );
+    }
+
+    #[test]
+    fn comma_separated_trims_and_skips_empty_entries() {
+        assert_eq!(comma_separated(" a, b ,,c ,"), ["a", "b", "c"]);
+        assert!(comma_separated("").is_empty());
+        assert!(comma_separated(" , ").is_empty());
+    }
+
+    #[test]
+    fn constant_labels_are_parsed() {
+        let labels = parse_constant_labels("region=eu-west-1, tier = gold").unwrap();
+        assert_eq!(
+            labels,
+            [
+                KeyValue::new("region", "eu-west-1"),
+                KeyValue::new("tier", "gold")
+            ]
+        );
+        assert!(parse_constant_labels("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_constant_labels_are_rejected() {
+        let error = |raw| parse_constant_labels(raw).unwrap_err().to_string();
+        assert_eq!(
+            error("region"),
+            "METRIC_CONSTANT_LABELS entry \"region\" is not name=value"
+        );
+        assert_eq!(
+            error("1region=eu"),
+            "Invalid label name in METRIC_CONSTANT_LABELS: \"1region\""
+        );
+        assert_eq!(
+            error("re-gion=eu"),
+            "Invalid label name in METRIC_CONSTANT_LABELS: \"re-gion\""
+        );
+        assert_eq!(
+            error("status=ok"),
+            "METRIC_CONSTANT_LABELS may not set the \"status\" label"
+        );
+        assert_eq!(
+            error("region=eu,region=us"),
+            "Duplicate label in METRIC_CONSTANT_LABELS: \"region\""
+        );
+        assert_eq!(
+            error("region="),
+            "Empty value for label \"region\" in METRIC_CONSTANT_LABELS"
+        );
+    }
+
+    #[test]
+    fn response_cache_is_disabled_by_default() {
+        let processing = processing_config().unwrap();
+        assert!(processing.response_cache.is_none());
+        assert!(processing.idempotency_store.is_none());
+    }
+
+    #[test]
+    fn cache_config_needs_a_ttl_and_entries() {
+        let cache = cache_config(30, 100).unwrap();
+        assert_eq!(cache.max_entries.get(), 100);
+        assert_eq!(cache.ttl, Duration::from_secs(30));
+        assert!(cache_config(30, 0).is_none());
+        assert!(cache_config(0, 100).is_none());
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::sync::mpsc::Sender;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tower::discover::Change;
+use tracing::{info, warn};
+
+use crate::{build_endpoint, ChannelTuning};
+
+type LookupFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Hostname resolution, abstracted so the refresh logic can run against a fake
+pub trait Resolver: Send + Sync + 'static {
+    fn lookup<'a>(&'a self, host: &'a str, port: u16) -> LookupFuture<'a>;
+}
+
+/// Resolves through the system resolver (`getaddrinfo`)
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn lookup<'a>(&'a self, host: &'a str, port: u16) -> LookupFuture<'a> {
+        Box::pin(async move { Ok(tokio::net::lookup_host((host, port)).await?.collect()) })
+    }
+}
+
+/// All distinct addresses `host` currently resolves to
+pub async fn resolve_endpoints(
+    resolver: &dyn Resolver,
+    host: &str,
+    port: u16,
+) -> io::Result<BTreeSet<SocketAddr>> {
+    Ok(resolver.lookup(host, port).await?.into_iter().collect())
+}
+
+/// Splits a `host:port` target, rejecting anything without a numeric port
+fn split_host_port(target: &str) -> Result<(String, u16), Box<dyn std::error::Error>> {
+    let (host, port) = target
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Downstream address {:?} is missing a port", target))?;
+    let port = port
+        .parse()
+        .map_err(|e| format!("Invalid port in downstream address {:?}: {}", target, e))?;
+    Ok((
+        host.trim_matches(|c| c == '[' || c == ']').to_string(),
+        port,
+    ))
+}
+
+/// Balanced channel over every IP the comma-separated `host:port` targets resolve
+/// to, re-resolved every `refresh`. Each refresh is diffed against the current
+/// endpoint set so only added/removed pods cause connection churn; an empty or
+/// failed resolution keeps the previous set rather than dropping all traffic.
+pub fn dns_balanced_channel(
+    addrs: &str,
+    tls: Option<&ClientTlsConfig>,
+    tuning: ChannelTuning,
+    refresh: Duration,
+    resolver: impl Resolver,
+) -> Result<Channel, Box<dyn std::error::Error>> {
+    let (channel, changes) = Channel::balance_channel(64);
+    spawn_dns_discovery(addrs, tls, tuning, refresh, resolver, changes)?;
+    Ok(channel)
+}
+
+/// Resolves the comma-separated `host:port` targets every `refresh` in the
+/// background, sending endpoint additions and removals to `changes`
+pub fn spawn_dns_discovery(
+    addrs: &str,
+    tls: Option<&ClientTlsConfig>,
+    tuning: ChannelTuning,
+    refresh: Duration,
+    resolver: impl Resolver,
+    changes: Sender<Change<SocketAddr, Endpoint>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let targets = addrs
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(split_host_port)
+        .collect::<Result<Vec<_>, _>>()?;
+    if targets.is_empty() {
+        return Err(format!("No downstream address in {:?}", addrs).into());
+    }
+
+    tokio::spawn(refresh_loop(
+        targets,
+        tls.cloned(),
+        tuning,
+        refresh,
+        resolver,
+        changes,
+    ));
+    Ok(())
+}
+
+async fn refresh_loop(
+    targets: Vec<(String, u16)>,
+    tls: Option<ClientTlsConfig>,
+    tuning: ChannelTuning,
+    refresh: Duration,
+    resolver: impl Resolver,
+    changes: Sender<Change<SocketAddr, Endpoint>>,
+) {
+    let mut current = BTreeSet::new();
+    let mut interval = tokio::time::interval(refresh);
+
+    loop {
+        interval.tick().await;
+
+        // Resolved address -> the hostname it came from (the TLS server name)
+        let mut resolved = BTreeMap::new();
+        for (host, port) in &targets {
+            match resolve_endpoints(&resolver, host, *port).await {
+                Ok(addrs) => resolved.extend(addrs.into_iter().map(|addr| (addr, host))),
+                Err(e) => warn!("[Service B] Failed to resolve {}:{}: {}", host, port, e),
+            }
+        }
+        if resolved.is_empty() {
+            continue;
+        }
+        let next: BTreeSet<SocketAddr> = resolved.keys().copied().collect();
+        let (removed, added) = endpoint_changes(&current, &next);
+        if removed.is_empty() && added.is_empty() {
+            continue;
+        }
+
+        for removed in removed {
+            if changes.send(Change::Remove(removed)).await.is_err() {
+                return;
+            }
+        }
+        for added in &added {
+            let tls = tls
+                .clone()
+                .map(|tls| tls.domain_name(resolved[added].as_str()));
+            let endpoint = match build_endpoint(&added.to_string(), tls.as_ref(), tuning) {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    warn!("[Service B] Skipping endpoint {}: {}", added, e);
+                    continue;
+                }
+            };
+            if changes
+                .send(Change::Insert(*added, endpoint))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        info!("[Service B] Downstream endpoints updated: {:?}", next);
+        current = next;
+    }
+}
+
+/// The endpoints to remove from `current` and add to it to reach `next`;
+/// those in both are left alone, keeping their connections
+fn endpoint_changes(
+    current: &BTreeSet<SocketAddr>,
+    next: &BTreeSet<SocketAddr>,
+) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    (
+        current.difference(next).copied().collect(),
+        next.difference(current).copied().collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// Answers each lookup of a host with the next of its scripted results,
+    /// repeating the last one once the script runs out
+    struct ScriptedResolver {
+        script: Mutex<BTreeMap<String, VecDeque<io::Result<Vec<SocketAddr>>>>>,
+    }
+
+    impl ScriptedResolver {
+        fn new<const N: usize>(script: [(&str, Vec<io::Result<Vec<SocketAddr>>>); N]) -> Self {
+            Self {
+                script: Mutex::new(
+                    script
+                        .into_iter()
+                        .map(|(host, results)| (host.to_string(), results.into()))
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    impl Resolver for ScriptedResolver {
+        fn lookup<'a>(&'a self, host: &'a str, port: u16) -> LookupFuture<'a> {
+            let mut script = self.script.lock().unwrap();
+            let results = script.get_mut(host).expect("unscripted host");
+            let result = if results.len() > 1 {
+                results.pop_front().unwrap()
+            } else {
+                match &results[0] {
+                    Ok(addrs) => Ok(addrs.clone()),
+                    Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+                }
+            };
+            let result = result.map(|addrs| {
+                addrs
+                    .into_iter()
+                    .map(|addr| SocketAddr::new(addr.ip(), port))
+                    .collect()
+            });
+            Box::pin(async move { result })
+        }
+    }
+
+    fn addr(last_octet: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last_octet], 50051))
+    }
+
+    fn not_found() -> io::Result<Vec<SocketAddr>> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "no such host"))
+    }
+
+    fn tuning() -> ChannelTuning {
+        ChannelTuning {
+            connect_timeout: Duration::from_secs(1),
+            tcp_keepalive: None,
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: Duration::from_secs(1),
+            keepalive_while_idle: false,
+        }
+    }
+
+    /// The next change sent, as its kind and endpoint
+    async fn next_change(
+        received: &mut mpsc::Receiver<Change<SocketAddr, Endpoint>>,
+    ) -> (&'static str, SocketAddr) {
+        match tokio::time::timeout(Duration::from_secs(1), received.recv()).await {
+            Ok(Some(Change::Insert(addr, _))) => ("insert", addr),
+            Ok(Some(Change::Remove(addr))) => ("remove", addr),
+            _ => panic!("no endpoint change within 1s"),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_endpoints_deduplicates_addresses() {
+        let resolver = ScriptedResolver::new([("svc", vec![Ok(vec![addr(2), addr(1), addr(2)])])]);
+
+        let endpoints = resolve_endpoints(&resolver, "svc", 50051).await.unwrap();
+
+        assert_eq!(endpoints, BTreeSet::from([addr(1), addr(2)]));
+    }
+
+    #[tokio::test]
+    async fn resolve_endpoints_passes_on_lookup_failures() {
+        let resolver = ScriptedResolver::new([("svc", vec![not_found()])]);
+
+        let error = resolve_endpoints(&resolver, "svc", 50051)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn endpoint_changes_leaves_unchanged_endpoints_alone() {
+        let current = BTreeSet::from([addr(1), addr(2)]);
+        let next = BTreeSet::from([addr(2), addr(3)]);
+
+        assert_eq!(
+            endpoint_changes(&current, &next),
+            (vec![addr(1)], vec![addr(3)])
+        );
+        assert_eq!(endpoint_changes(&next, &next), (vec![], vec![]));
+        assert_eq!(
+            endpoint_changes(&BTreeSet::new(), &next),
+            (vec![], vec![addr(2), addr(3)])
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_sends_only_the_endpoint_diff() {
+        let resolver = ScriptedResolver::new([(
+            "svc",
+            vec![
+                Ok(vec![addr(1), addr(2)]),
+                // Neither a failed nor an empty resolution drops the endpoints
+                not_found(),
+                Ok(Vec::new()),
+                Ok(vec![addr(2), addr(3)]),
+            ],
+        )]);
+        let (changes, mut received) = mpsc::channel(16);
+        spawn_dns_discovery(
+            "svc:50051",
+            None,
+            tuning(),
+            Duration::from_millis(10),
+            resolver,
+            changes,
+        )
+        .unwrap();
+
+        assert_eq!(next_change(&mut received).await, ("insert", addr(1)));
+        assert_eq!(next_change(&mut received).await, ("insert", addr(2)));
+        assert_eq!(next_change(&mut received).await, ("remove", addr(1)));
+        assert_eq!(next_change(&mut received).await, ("insert", addr(3)));
+
+        // Resolving the same set again changes nothing
+        let repeated = tokio::time::timeout(Duration::from_millis(100), received.recv()).await;
+        assert!(repeated.is_err(), "unexpected change after the set settled");
+    }
+}
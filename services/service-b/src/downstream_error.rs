@@ -0,0 +1,71 @@
+use std::fmt;
+
+use tonic::{Code, Status};
+
+/// Why a call to Service D or E failed
+#[derive(Debug)]
+pub enum DownstreamError {
+    /// The downstream couldn't be reached (`UNAVAILABLE`)
+    Connect(Status),
+    /// The downstream answered with an error status
+    Rpc { code: Code, message: String },
+    /// The call succeeded but the downstream reported a business-level failure
+    /// (`success = false`), e.g. Service D rejecting the data
+    Rejected { message: String },
+    /// The call ran out of time, either locally or at the downstream
+    Timeout(Status),
+    /// The circuit breaker rejected the call without sending it
+    CircuitOpen,
+}
+
+impl DownstreamError {
+    /// The gRPC status code the failure maps to
+    pub fn code(&self) -> Code {
+        match self {
+            DownstreamError::Connect(status) | DownstreamError::Timeout(status) => status.code(),
+            DownstreamError::Rpc { code, .. } => *code,
+            DownstreamError::Rejected { .. } => Code::FailedPrecondition,
+            DownstreamError::CircuitOpen => Code::Unavailable,
+        }
+    }
+
+    /// The `kind` label of the downstream error counter
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DownstreamError::Connect(_) => "connection",
+            DownstreamError::Rpc { .. } => "rpc",
+            DownstreamError::Rejected { .. } => "rejected",
+            DownstreamError::Timeout(_) => "timeout",
+            DownstreamError::CircuitOpen => "circuit_open",
+        }
+    }
+}
+
+impl From<Status> for DownstreamError {
+    fn from(status: Status) -> Self {
+        match status.code() {
+            Code::Unavailable => DownstreamError::Connect(status),
+            Code::DeadlineExceeded => DownstreamError::Timeout(status),
+            code => DownstreamError::Rpc {
+                code,
+                message: status.message().to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DownstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownstreamError::Connect(status) => {
+                write!(f, "connection failed: {}", status.message())
+            }
+            DownstreamError::Rpc { code, message } => write!(f, "{:?}: {}", code, message),
+            DownstreamError::Rejected { message } => write!(f, "returned failure: {}", message),
+            DownstreamError::Timeout(status) => f.write_str(status.message()),
+            DownstreamError::CircuitOpen => f.write_str("circuit open"),
+        }
+    }
+}
+
+impl std::error::Error for DownstreamError {}
@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tonic_health::server::HealthReporter;
+use tracing::{info, warn};
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::grpcarch::service_b_server::ServiceBServer;
+use crate::ServiceBImpl;
+
+/// How often the breakers are checked; an outage is reported within this long
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Reports grpcarch.ServiceB as NOT_SERVING while both the Service D and E
+/// breakers are open, since no request can then do useful work, so load
+/// balancers route away until a downstream recovers. SERVING is reported again
+/// once either breaker's cooldown runs out: without traffic the half-open
+/// probe that would close it never comes, and if it fails the breaker
+/// re-opens and the outage is reported again.
+///
+/// Only acts once the service is ready (and not while it drains), so startup
+/// and shutdown keep their own readiness reporting.
+pub async fn watch(
+    mut reporter: HealthReporter,
+    ready: Arc<AtomicBool>,
+    breakers: [Arc<CircuitBreaker>; 2],
+) {
+    let mut outage = false;
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        poll.tick().await;
+        let both_open = breakers.iter().all(|breaker| breaker.is_open());
+        if both_open == outage || !ready.load(Ordering::Relaxed) {
+            continue;
+        }
+        outage = both_open;
+        if outage {
+            warn!("[Service B] Service D and E circuits both open, reporting NOT_SERVING");
+            reporter
+                .set_not_serving::<ServiceBServer<ServiceBImpl>>()
+                .await;
+        } else {
+            info!("[Service B] A downstream circuit is no longer open, reporting SERVING");
+            reporter.set_serving::<ServiceBServer<ServiceBImpl>>().await;
+        }
+    }
+}
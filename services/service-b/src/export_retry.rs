@@ -0,0 +1,120 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use opentelemetry_sdk::export::logs::{LogBatch, LogExporter};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::logs::LogResult;
+use opentelemetry_sdk::Resource;
+
+/// Delay before the first retry, doubling for each one after
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How failed OTLP exports are retried before the batch is dropped
+#[derive(Clone, Copy, Debug)]
+pub struct ExportRetry {
+    pub max_retries: u32,
+}
+
+impl ExportRetry {
+    fn backoff(self, attempt: u32) -> Duration {
+        BASE_BACKOFF.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+/// Retries failed span exports with exponential backoff, so a batch survives a
+/// collector restart instead of being dropped on the first refused
+/// connection. The batch processor's export timeout still bounds the whole
+/// attempt, retries included.
+#[derive(Debug)]
+pub struct RetryingSpanExporter<E> {
+    // Shared with the export future, which has to outlive `&mut self`; the
+    // lock is only held while starting an attempt
+    inner: Arc<Mutex<E>>,
+    retry: ExportRetry,
+}
+
+impl<E> RetryingSpanExporter<E> {
+    pub fn new(inner: E, retry: ExportRetry) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            retry,
+        }
+    }
+}
+
+impl<E: SpanExporter + 'static> SpanExporter for RetryingSpanExporter<E> {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send>> {
+        let inner = self.inner.clone();
+        let retry = self.retry;
+        Box::pin(async move {
+            let mut attempt = 0;
+            loop {
+                let export = inner.lock().unwrap().export(batch.clone());
+                match export.await {
+                    Err(_) if attempt < retry.max_retries => {
+                        tokio::time::sleep(retry.backoff(attempt)).await;
+                        attempt += 1;
+                    }
+                    result => return result,
+                }
+            }
+        })
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.lock().unwrap().shutdown();
+    }
+
+    fn force_flush(&mut self) -> Pin<Box<dyn Future<Output = ExportResult> + Send>> {
+        self.inner.lock().unwrap().force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.lock().unwrap().set_resource(resource);
+    }
+}
+
+/// The log counterpart of `RetryingSpanExporter`
+#[derive(Debug)]
+pub struct RetryingLogExporter<E> {
+    inner: E,
+    retry: ExportRetry,
+}
+
+impl<E> RetryingLogExporter<E> {
+    pub fn new(inner: E, retry: ExportRetry) -> Self {
+        Self { inner, retry }
+    }
+}
+
+#[tonic::async_trait]
+impl<E: LogExporter> LogExporter for RetryingLogExporter<E> {
+    async fn export(&mut self, batch: LogBatch<'_>) -> LogResult<()> {
+        // A `LogBatch` is consumed by each attempt, so retries rebuild it from
+        // the same records
+        let records: Vec<_> = batch.iter().collect();
+        let mut attempt = 0;
+        loop {
+            match self.inner.export(LogBatch::new(&records)).await {
+                Err(_) if attempt < self.retry.max_retries => {
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
@@ -0,0 +1,73 @@
+use std::env;
+use std::error::Error;
+
+/// Whether a downstream's failure fails the request it was called for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Failures mark the response unsuccessful (fail closed)
+    #[default]
+    Required,
+    /// Failures are logged and counted but don't affect `success` (fail open)
+    Optional,
+}
+
+/// Per-downstream failure policies
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DownstreamPolicies {
+    pub service_d: FailurePolicy,
+    pub service_e: FailurePolicy,
+}
+
+/// Policies from `DOWNSTREAM_FAILURE_POLICY`, a comma-separated list of
+/// `<downstream>=<required|optional>` pairs, e.g. `service-d=optional`.
+/// Downstreams that aren't listed are required.
+pub fn load_failure_policies() -> Result<DownstreamPolicies, Box<dyn Error>> {
+    let mut policies = DownstreamPolicies::default();
+    let Ok(raw) = env::var("DOWNSTREAM_FAILURE_POLICY") else {
+        return Ok(policies);
+    };
+
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let invalid = || format!("Invalid value for DOWNSTREAM_FAILURE_POLICY: {:?}", entry);
+        let (downstream, policy) = entry.split_once('=').ok_or_else(invalid)?;
+        let policy = match policy.trim() {
+            "required" => FailurePolicy::Required,
+            "optional" => FailurePolicy::Optional,
+            _ => return Err(invalid().into()),
+        };
+        match downstream.trim() {
+            "service-d" => policies.service_d = policy,
+            "service-e" => policies.service_e = policy,
+            _ => return Err(invalid().into()),
+        }
+    }
+    Ok(policies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One test, since every case sets the same process-wide variable
+    #[test]
+    fn policies_are_loaded_from_the_environment() {
+        env::remove_var("DOWNSTREAM_FAILURE_POLICY");
+        let policies = load_failure_policies().unwrap();
+        assert_eq!(policies.service_d, FailurePolicy::Required);
+        assert_eq!(policies.service_e, FailurePolicy::Required);
+
+        env::set_var("DOWNSTREAM_FAILURE_POLICY", " service-e = optional ,");
+        let policies = load_failure_policies().unwrap();
+        assert_eq!(policies.service_d, FailurePolicy::Required);
+        assert_eq!(policies.service_e, FailurePolicy::Optional);
+
+        for invalid in ["service-e", "service-e=maybe", "service-f=optional"] {
+            env::set_var("DOWNSTREAM_FAILURE_POLICY", invalid);
+            assert_eq!(
+                load_failure_policies().unwrap_err().to_string(),
+                format!("Invalid value for DOWNSTREAM_FAILURE_POLICY: {:?}", invalid)
+            );
+        }
+        env::remove_var("DOWNSTREAM_FAILURE_POLICY");
+    }
+}
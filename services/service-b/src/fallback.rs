@@ -0,0 +1,57 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use lru::LruCache;
+
+use crate::grpcarch::ProcessRequest;
+
+/// Distinct computations whose last result is remembered
+const LAST_GOOD_CAPACITY: NonZeroUsize = match NonZeroUsize::new(1000) {
+    Some(capacity) => capacity,
+    None => unreachable!(),
+};
+
+/// Stand-in Service E results for when a compute call fails: the last
+/// successful result for the same operation and inputs, or else a configured
+/// default value
+pub struct ComputeFallback {
+    default_value: Option<f64>,
+    last_good: Mutex<LruCache<u64, Vec<f64>>>,
+}
+
+impl ComputeFallback {
+    pub fn new(default_value: Option<f64>) -> Self {
+        Self {
+            default_value,
+            last_good: Mutex::new(LruCache::new(LAST_GOOD_CAPACITY)),
+        }
+    }
+
+    pub fn remember(&self, req: &ProcessRequest, output_values: &[f64]) {
+        self.last_good
+            .lock()
+            .unwrap()
+            .put(computation_key(req), output_values.to_vec());
+    }
+
+    /// `None` when there is neither a previous result nor a default
+    pub fn fallback(&self, req: &ProcessRequest) -> Option<Vec<f64>> {
+        self.last_good
+            .lock()
+            .unwrap()
+            .get(&computation_key(req))
+            .cloned()
+            .or_else(|| self.default_value.map(|value| vec![value]))
+    }
+}
+
+fn computation_key(req: &ProcessRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    req.operation.hash(&mut hasher);
+    for value in &req.input_values {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
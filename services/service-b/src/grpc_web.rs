@@ -0,0 +1,101 @@
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+
+use tonic::codegen::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// How long browsers may cache a preflight response
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Response headers a browser client must be able to read. The status headers
+/// matter for trailers-only error responses, where the status arrives in the
+/// headers rather than the encoded trailer frame.
+const EXPOSED_HEADERS: [&str; 4] = [
+    "grpc-status",
+    "grpc-message",
+    "grpc-status-details-bin",
+    "x-request-id",
+];
+
+/// gRPC-Web support from `ENABLE_GRPC_WEB`, with the CORS origins allowed to
+/// call from `GRPC_WEB_ALLOWED_ORIGINS` (comma-separated). Returns `None` when
+/// gRPC-Web is disabled. Without an origin list any origin is allowed, which
+/// is safe here since no browser credentials are accepted.
+pub fn load_grpc_web_cors() -> Result<Option<CorsLayer>, Box<dyn Error>> {
+    let enabled = env::var("ENABLE_GRPC_WEB")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if !enabled {
+        return Ok(None);
+    }
+
+    let allow_origin = match env::var("GRPC_WEB_ALLOWED_ORIGINS") {
+        Err(_) => AllowOrigin::any(),
+        Ok(raw) => {
+            let origins = raw
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(|origin| {
+                    HeaderValue::from_str(origin).map_err(|e| {
+                        format!(
+                            "Invalid origin in GRPC_WEB_ALLOWED_ORIGINS {:?}: {}",
+                            origin, e
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if origins.is_empty() {
+                return Err("GRPC_WEB_ALLOWED_ORIGINS is set but contains no origins".into());
+            }
+            AllowOrigin::list(origins)
+        }
+    };
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::POST, Method::OPTIONS])
+            .allow_headers(Any)
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+            .max_age(PREFLIGHT_MAX_AGE),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One test, since every case sets the same process-wide variables
+    #[test]
+    fn cors_is_loaded_from_the_environment() {
+        env::remove_var("ENABLE_GRPC_WEB");
+        env::remove_var("GRPC_WEB_ALLOWED_ORIGINS");
+        assert!(load_grpc_web_cors().unwrap().is_none());
+
+        env::set_var("ENABLE_GRPC_WEB", "TRUE");
+        assert!(load_grpc_web_cors().unwrap().is_some());
+
+        env::set_var(
+            "GRPC_WEB_ALLOWED_ORIGINS",
+            "https://a.example, https://b.example",
+        );
+        assert!(load_grpc_web_cors().unwrap().is_some());
+
+        env::set_var("GRPC_WEB_ALLOWED_ORIGINS", " , ");
+        assert_eq!(
+            load_grpc_web_cors().unwrap_err().to_string(),
+            "GRPC_WEB_ALLOWED_ORIGINS is set but contains no origins"
+        );
+
+        env::set_var("GRPC_WEB_ALLOWED_ORIGINS", "https://a.\u{1}example");
+        let error = load_grpc_web_cors().unwrap_err().to_string();
+        assert!(error.starts_with("Invalid origin in GRPC_WEB_ALLOWED_ORIGINS"));
+
+        env::set_var("ENABLE_GRPC_WEB", "false");
+        assert!(load_grpc_web_cors().unwrap().is_none());
+        env::remove_var("ENABLE_GRPC_WEB");
+        env::remove_var("GRPC_WEB_ALLOWED_ORIGINS");
+    }
+}
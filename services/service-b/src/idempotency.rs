@@ -0,0 +1,176 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use tokio::sync::watch;
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+
+use crate::grpcarch::ProcessResponse;
+
+/// Client-chosen key identifying one logical request across its retries
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// `ProcessData` responses remembered by `idempotency-key` for a fixed TTL, so
+/// a client retrying after a timeout gets the original answer instead of
+/// repeating the downstream calls. Unlike the response cache this applies to
+/// non-idempotent work too, since the client opts in per request.
+///
+/// A request arriving while another with the same key is still being processed
+/// waits for it. If the first one doesn't finish (e.g. it was cancelled), one
+/// of the waiters takes over.
+pub struct IdempotencyStore {
+    entries: Mutex<LruCache<String, Entry>>,
+    ttl: Duration,
+}
+
+/// Hash of the request the key was first used with (see `cache_key`), so a
+/// key reused for different work is rejected rather than answered wrongly
+type Fingerprint = u64;
+
+enum Entry {
+    InFlight {
+        fingerprint: Fingerprint,
+        done: watch::Receiver<Option<ProcessResponse>>,
+    },
+    Done {
+        fingerprint: Fingerprint,
+        stored_at: Instant,
+        response: Box<ProcessResponse>,
+    },
+}
+
+/// Outcome of `IdempotencyStore::claim`
+pub enum Claim {
+    /// An earlier request with this key already produced this response
+    Replay(Box<ProcessResponse>),
+    /// This request is the one to process the key
+    Owner(IdempotencyClaim),
+}
+
+impl IdempotencyStore {
+    pub fn new(max_entries: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(max_entries)),
+            ttl,
+        }
+    }
+
+    pub async fn claim(
+        self: &Arc<Self>,
+        key: &str,
+        fingerprint: Fingerprint,
+    ) -> Result<Claim, Status> {
+        loop {
+            let mut done = {
+                let mut entries = self.entries.lock().unwrap();
+                // Once expired, the key is free to be reused for anything
+                if matches!(
+                    entries.peek(key),
+                    Some(Entry::Done { stored_at, .. }) if stored_at.elapsed() >= self.ttl
+                ) {
+                    entries.pop(key);
+                }
+                match entries.get(key) {
+                    Some(entry) if entry.fingerprint() != fingerprint => {
+                        return Err(Status::failed_precondition(format!(
+                            "{} {:?} was already used for a different request",
+                            IDEMPOTENCY_KEY_HEADER, key
+                        )));
+                    }
+                    Some(Entry::Done { response, .. }) => {
+                        return Ok(Claim::Replay(response.clone()));
+                    }
+                    Some(Entry::InFlight { done, .. }) => done.clone(),
+                    None => {
+                        let (sender, done) = watch::channel(None);
+                        entries.put(
+                            key.to_string(),
+                            Entry::InFlight {
+                                fingerprint,
+                                done: done.clone(),
+                            },
+                        );
+                        return Ok(Claim::Owner(IdempotencyClaim {
+                            store: self.clone(),
+                            key: key.to_string(),
+                            fingerprint,
+                            sender,
+                            done,
+                        }));
+                    }
+                }
+            };
+
+            // An error means the owner gave up without a response; go round
+            // again to take over the key
+            let response = done
+                .wait_for(Option::is_some)
+                .await
+                .ok()
+                .and_then(|response| response.clone());
+            if let Some(response) = response {
+                return Ok(Claim::Replay(Box::new(response)));
+            }
+        }
+    }
+}
+
+impl Entry {
+    fn fingerprint(&self) -> Fingerprint {
+        match self {
+            Entry::InFlight { fingerprint, .. } | Entry::Done { fingerprint, .. } => *fingerprint,
+        }
+    }
+}
+
+/// Exclusive right to process a key. Dropping it without calling `complete`
+/// releases the key, waking anyone waiting on it.
+pub struct IdempotencyClaim {
+    store: Arc<IdempotencyStore>,
+    key: String,
+    fingerprint: Fingerprint,
+    sender: watch::Sender<Option<ProcessResponse>>,
+    /// Identifies this claim's entry, which may since have been evicted
+    done: watch::Receiver<Option<ProcessResponse>>,
+}
+
+impl IdempotencyClaim {
+    /// Stores the response for replay and hands it to any waiting requests
+    pub fn complete(self, response: &ProcessResponse) {
+        self.store.entries.lock().unwrap().put(
+            self.key.clone(),
+            Entry::Done {
+                fingerprint: self.fingerprint,
+                stored_at: Instant::now(),
+                response: Box::new(response.clone()),
+            },
+        );
+        self.sender.send_replace(Some(response.clone()));
+    }
+}
+
+impl Drop for IdempotencyClaim {
+    fn drop(&mut self) {
+        if self.sender.borrow().is_some() {
+            return;
+        }
+        let mut entries = self.store.entries.lock().unwrap();
+        if let Some(Entry::InFlight { done, .. }) = entries.peek(&self.key) {
+            if done.same_channel(&self.done) {
+                entries.pop(&self.key);
+            }
+        }
+    }
+}
+
+/// The request's `idempotency-key`, if it sent a non-empty one
+pub fn idempotency_key(metadata: &MetadataMap) -> Option<String> {
+    metadata
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(String::from)
+}
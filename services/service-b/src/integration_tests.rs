@@ -0,0 +1,656 @@
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, Notify};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::server::Router;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::grpcarch::service_b_client::ServiceBClient;
+use crate::grpcarch::service_d_server::{ServiceD, ServiceDServer};
+use crate::grpcarch::service_e_server::{ServiceE, ServiceEServer};
+use crate::grpcarch::{
+    ComputeRequest, ComputeResponse, DataPayload, ProcessRequest, ProcessResponse, ResponseStatus,
+    ValidationRequest, ValidationResponse,
+};
+use crate::idempotency::IDEMPOTENCY_KEY_HEADER;
+use crate::*;
+
+/// Output every successful stub Service E computation returns
+const STUB_OUTPUT: [f64; 1] = [15.0];
+
+/// How a stub downstream answers every call
+#[derive(Clone, Copy)]
+struct Behaviour {
+    delay: Duration,
+    success: bool,
+}
+
+impl Behaviour {
+    const OK: Self = Self {
+        delay: Duration::ZERO,
+        success: true,
+    };
+
+    fn delayed(delay: Duration) -> Self {
+        Self { delay, ..Self::OK }
+    }
+
+    fn failing() -> Self {
+        Self {
+            success: false,
+            ..Self::OK
+        }
+    }
+
+    fn status(self) -> ResponseStatus {
+        ResponseStatus {
+            success: self.success,
+            message: if self.success {
+                String::new()
+            } else {
+                String::from("stub failure")
+            },
+            error_code: 0,
+        }
+    }
+}
+
+/// What a stub has seen, shared with the test driving it
+struct Calls<T> {
+    received: Mutex<Vec<T>>,
+    /// Signalled as each call arrives
+    started: Notify,
+    /// Calls dropped before the stub answered them
+    aborted: AtomicUsize,
+}
+
+impl<T> Default for Calls<T> {
+    fn default() -> Self {
+        Self {
+            received: Mutex::new(Vec::new()),
+            started: Notify::new(),
+            aborted: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Clone> Calls<T> {
+    fn received(&self) -> Vec<T> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl<T> Calls<T> {
+    /// Records `request`, then answers with `response` after the behaviour's
+    /// delay, counting the call as aborted if it is dropped before then
+    async fn answer<R>(&self, request: T, behaviour: Behaviour, response: R) -> R {
+        self.received.lock().unwrap().push(request);
+        self.started.notify_one();
+        let mut pending = AbortGuard {
+            aborted: &self.aborted,
+            finished: false,
+        };
+        tokio::time::sleep(behaviour.delay).await;
+        pending.finished = true;
+        response
+    }
+}
+
+struct AbortGuard<'a> {
+    aborted: &'a AtomicUsize,
+    finished: bool,
+}
+
+impl Drop for AbortGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.aborted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct StubD {
+    behaviour: Behaviour,
+    calls: Arc<Calls<ValidationRequest>>,
+}
+
+#[tonic::async_trait]
+impl ServiceD for StubD {
+    async fn validate_data(
+        &self,
+        request: Request<ValidationRequest>,
+    ) -> Result<Response<ValidationResponse>, Status> {
+        let response = ValidationResponse {
+            status: Some(self.behaviour.status()),
+            is_valid: self.behaviour.success,
+            errors: Vec::new(),
+        };
+        let response = self
+            .calls
+            .answer(request.into_inner(), self.behaviour, response)
+            .await;
+        Ok(Response::new(response))
+    }
+}
+
+struct StubE {
+    behaviour: Behaviour,
+    calls: Arc<Calls<ComputeRequest>>,
+}
+
+#[tonic::async_trait]
+impl ServiceE for StubE {
+    async fn compute(
+        &self,
+        request: Request<ComputeRequest>,
+    ) -> Result<Response<ComputeResponse>, Status> {
+        let response = ComputeResponse {
+            status: Some(self.behaviour.status()),
+            output_values: if self.behaviour.success {
+                STUB_OUTPUT.to_vec()
+            } else {
+                Vec::new()
+            },
+            metrics: None,
+        };
+        let response = self
+            .calls
+            .answer(request.into_inner(), self.behaviour, response)
+            .await;
+        Ok(Response::new(response))
+    }
+}
+
+/// Serves `router` on an ephemeral localhost port, returning the address once
+/// it is bound
+async fn spawn_server(router: Router) -> SocketAddr {
+    let (bound_tx, bound_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        bound_tx.send(listener.local_addr().unwrap()).unwrap();
+        router
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    bound_rx.await.unwrap()
+}
+
+async fn spawn_stub_e(behaviour: Behaviour) -> (SocketAddr, Arc<Calls<ComputeRequest>>) {
+    let calls = Arc::new(Calls::default());
+    let addr = spawn_server(Server::builder().add_service(ServiceEServer::new(StubE {
+        behaviour,
+        calls: calls.clone(),
+    })))
+    .await;
+    (addr, calls)
+}
+
+/// A `ServiceBImpl` wired to in-process Service D and E stubs
+struct Harness {
+    service: ServiceBImpl,
+    d: Arc<Calls<ValidationRequest>>,
+    e: Arc<Calls<ComputeRequest>>,
+}
+
+impl Harness {
+    async fn start(d: Behaviour, e: Behaviour) -> Self {
+        Self::with_config(d, e, service_b_config()).await
+    }
+
+    async fn with_config(d: Behaviour, e: Behaviour, config: ServiceBConfig) -> Self {
+        Self::build(d, e, |fan_out| fan_out, config).await
+    }
+
+    /// `fan_out` adjusts the `FanOutProcessor` settings the stubs are wired into
+    async fn build(
+        d: Behaviour,
+        e: Behaviour,
+        fan_out: impl FnOnce(FanOutConfig) -> FanOutConfig,
+        config: ServiceBConfig,
+    ) -> Self {
+        let d_calls = Arc::new(Calls::default());
+        let d_addr = spawn_server(Server::builder().add_service(ServiceDServer::new(StubD {
+            behaviour: d,
+            calls: d_calls.clone(),
+        })))
+        .await;
+        let (e_addr, e_calls) = spawn_stub_e(e).await;
+
+        let metrics = test_metrics();
+        let processor = FanOutProcessor::new(
+            fan_out(fan_out_config(&d_addr.to_string(), &e_addr.to_string())),
+            metrics.clone(),
+        )
+        .unwrap();
+        Self {
+            service: ServiceBImpl::new(config, Arc::new(processor), metrics),
+            d: d_calls,
+            e: e_calls,
+        }
+    }
+
+    async fn process(&self, request: Request<ProcessRequest>) -> ProcessResponse {
+        self.service
+            .process_data(request)
+            .await
+            .unwrap()
+            .into_inner()
+    }
+}
+
+fn test_metrics() -> Arc<ServiceBMetrics> {
+    Arc::new(ServiceBMetrics::new(
+        opentelemetry::global::meter("service-b-test"),
+        0.2,
+        Vec::new(),
+    ))
+}
+
+/// No retries, hedging or breaker trips, so every call reaches the stubs
+/// exactly once
+fn fan_out_config(service_d_addr: &str, service_e_addr: &str) -> FanOutConfig {
+    FanOutConfig {
+        service_d_addr: service_d_addr.to_string(),
+        service_e_addr: service_e_addr.to_string(),
+        service_d_timeout: Duration::from_secs(2),
+        service_e_timeout: Duration::from_secs(2),
+        service_e_hedge_delay: None,
+        service_e_canary: None,
+        retry_policy: RetryPolicy {
+            max_retries: 0,
+            budget: None,
+            rng: shared_rng(Some(0)),
+        },
+        breaker_config: CircuitBreakerConfig {
+            failure_threshold: u32::MAX,
+            cooldown: Duration::from_secs(1),
+        },
+        client_tls: None,
+        channel_tuning: ChannelTuning {
+            connect_timeout: Duration::from_secs(1),
+            tcp_keepalive: None,
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: Duration::from_secs(1),
+            keepalive_while_idle: false,
+        },
+        dns_refresh: None,
+        routing: DownstreamRouting::Balanced,
+        compression: None,
+        rng: shared_rng(Some(0)),
+        failure_policies: DownstreamPolicies::default(),
+        processor_id: String::from("test"),
+        compute_fallback: None,
+        max_message_bytes: 4 * 1024 * 1024,
+        identity: ServiceIdentity {
+            name: String::from("service-b"),
+            version: String::from("test"),
+        },
+    }
+}
+
+fn service_b_config() -> ServiceBConfig {
+    ServiceBConfig {
+        response_cache: None,
+        baggage_span_attributes: Vec::new(),
+        processor_id: String::from("test"),
+        rate_limit_rps: None,
+        rate_limit_labelled_callers: Vec::new(),
+        payload_redactor: None,
+        idempotency_store: None,
+        slow_request_threshold: None,
+        dry_run: false,
+        recent_requests: None,
+    }
+}
+
+fn process_request(id: &str) -> ProcessRequest {
+    ProcessRequest {
+        payload: Some(DataPayload {
+            id: id.to_string(),
+            content: String::from("payload"),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn downstream<'a>(response: &'a ProcessResponse, name: &str) -> &'a DownstreamResult {
+    response
+        .downstream_results
+        .iter()
+        .find(|result| result.name == name)
+        .unwrap()
+}
+
+/// The `status` of each request in `recent`, newest first
+fn recorded_statuses(recent: &RecentRequests) -> Vec<String> {
+    recent
+        .to_json(None)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["status"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn both_downstreams_succeed() {
+    let harness = Harness::start(Behaviour::OK, Behaviour::OK).await;
+
+    let response = harness
+        .process(Request::new(process_request("item-1")))
+        .await;
+
+    let status = response.status.unwrap();
+    assert!(status.success, "{}", status.message);
+    assert_eq!(response.output_values, STUB_OUTPUT);
+    assert!(response.downstream_results.iter().all(|r| r.success));
+    assert_eq!(response.result.unwrap().id, "processed-item-1");
+    assert_eq!(harness.d.received().len(), 1);
+    assert_eq!(harness.e.received().len(), 1);
+}
+
+#[tokio::test]
+async fn service_e_failure_is_a_partial_failure() {
+    let harness = Harness::start(Behaviour::OK, Behaviour::failing()).await;
+
+    let response = harness
+        .process(Request::new(process_request("item-1")))
+        .await;
+
+    let status = response.status.as_ref().unwrap();
+    assert!(!status.success);
+    assert!(
+        status.message.starts_with("Partial failure"),
+        "{}",
+        status.message
+    );
+    assert!(
+        status.message.contains("stub failure"),
+        "{}",
+        status.message
+    );
+    assert!(response.output_values.is_empty());
+    assert!(!downstream(&response, "service-e").success);
+    assert!(downstream(&response, "service-d").success);
+}
+
+#[tokio::test]
+async fn downstreams_are_called_concurrently() {
+    let delay = Duration::from_millis(100);
+    let harness = Harness::start(Behaviour::delayed(delay), Behaviour::delayed(delay)).await;
+
+    let start = Instant::now();
+    let response = harness
+        .process(Request::new(process_request("item-1")))
+        .await;
+    let elapsed = start.elapsed();
+
+    assert!(response.status.unwrap().success);
+    // One delay plus the 10-20ms of simulated local work; run one after the
+    // other, the calls alone would take 200ms
+    assert!(elapsed >= delay, "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(180), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn caller_validation_rules_reach_service_d() {
+    let harness = Harness::start(Behaviour::OK, Behaviour::OK).await;
+    let rules = vec![String::from("schema"), String::from("range")];
+
+    harness
+        .process(Request::new(ProcessRequest {
+            validation_rules: rules.clone(),
+            ..process_request("item-1")
+        }))
+        .await;
+
+    let received = harness.d.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].validation_rules, rules);
+}
+
+#[tokio::test]
+async fn caller_attributes_are_kept_in_the_result() {
+    let harness = Harness::start(Behaviour::OK, Behaviour::OK).await;
+    let mut request = process_request("item-1");
+    let payload = request.payload.as_mut().unwrap();
+    payload
+        .attributes
+        .insert(String::from("origin"), String::from("caller"));
+
+    let response = harness.process(Request::new(request)).await;
+
+    let attributes = response.result.unwrap().attributes;
+    assert_eq!(attributes.get("origin").map(String::as_str), Some("caller"));
+    assert!(attributes.contains_key("processed_at"));
+}
+
+#[tokio::test]
+async fn caller_cancellation_aborts_downstream_calls() {
+    let slow = Behaviour::delayed(Duration::from_secs(5));
+    let harness = Harness::start(slow, slow).await;
+    let addr =
+        spawn_server(Server::builder().add_service(ServiceBServer::new(harness.service.clone())))
+            .await;
+    let mut client = ServiceBClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    // Cancelled 5ms into the downstream calls, by dropping the client call
+    let call = client.process_data(process_request("item-1"));
+    tokio::select! {
+        result = call => panic!("call finished before it was cancelled: {:?}", result),
+        _ = async {
+            harness.e.started.notified().await;
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        } => {}
+    }
+
+    let aborted = async {
+        while harness.d.aborted.load(Ordering::Relaxed) == 0
+            || harness.e.aborted.load(Ordering::Relaxed) == 0
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(1), aborted)
+        .await
+        .expect("downstream calls kept running after the caller cancelled");
+}
+
+#[tokio::test]
+async fn slow_service_e_is_abandoned_at_the_caller_deadline() {
+    let harness = Harness::start(Behaviour::OK, Behaviour::delayed(Duration::from_secs(2))).await;
+    let mut request = Request::new(process_request("item-1"));
+    request.set_timeout(Duration::from_millis(200));
+
+    let start = Instant::now();
+    let response = harness.process(request).await;
+
+    assert!(
+        start.elapsed() < Duration::from_secs(1),
+        "{:?}",
+        start.elapsed()
+    );
+    let service_e = downstream(&response, "service-e");
+    assert!(!service_e.success);
+    assert_eq!(
+        service_e.error_code,
+        code_for(tonic::Code::DeadlineExceeded)
+    );
+    assert!(downstream(&response, "service-d").success);
+    assert!(!response.status.unwrap().success);
+}
+
+#[tokio::test]
+async fn replayed_partial_failure_is_recorded_as_one() {
+    let recent = Arc::new(RecentRequests::new(NonZeroUsize::new(8).unwrap()));
+    let config = ServiceBConfig {
+        idempotency_store: Some(ResponseCacheConfig {
+            max_entries: NonZeroUsize::new(8).unwrap(),
+            ttl: Duration::from_secs(60),
+        }),
+        recent_requests: Some(recent.clone()),
+        ..service_b_config()
+    };
+    let harness = Harness::with_config(Behaviour::OK, Behaviour::failing(), config).await;
+    let request = || {
+        let mut request = Request::new(process_request("item-1"));
+        request
+            .metadata_mut()
+            .insert(IDEMPOTENCY_KEY_HEADER, "key-1".parse().unwrap());
+        request
+    };
+
+    let first = harness.process(request()).await;
+    let replayed = harness.process(request()).await;
+
+    assert_eq!(replayed, first);
+    assert_eq!(harness.e.received().len(), 1);
+    assert_eq!(
+        recorded_statuses(&recent),
+        ["partial_failure", "partial_failure"]
+    );
+}
+
+#[tokio::test]
+async fn cache_hits_and_dry_runs_are_recorded() {
+    let recent = Arc::new(RecentRequests::new(NonZeroUsize::new(8).unwrap()));
+    let config = ServiceBConfig {
+        response_cache: Some(ResponseCacheConfig {
+            max_entries: NonZeroUsize::new(8).unwrap(),
+            ttl: Duration::from_secs(60),
+        }),
+        recent_requests: Some(recent.clone()),
+        ..service_b_config()
+    };
+    let harness = Harness::with_config(Behaviour::OK, Behaviour::OK, config).await;
+    let mut dry_run = Request::new(process_request("item-2"));
+    dry_run
+        .metadata_mut()
+        .insert(DRY_RUN_HEADER, "true".parse().unwrap());
+
+    harness
+        .process(Request::new(process_request("item-1")))
+        .await;
+    harness
+        .process(Request::new(process_request("item-1")))
+        .await;
+    harness.process(dry_run).await;
+
+    assert_eq!(harness.e.received().len(), 1);
+    assert_eq!(recorded_statuses(&recent), ["dry_run", "ok", "ok"]);
+}
+
+/// Request ids of the calls mirrored to a canary taking `mirror_percent` of
+/// 20 sequential requests, with the RNG seeded from `seed`
+async fn mirrored_request_ids(seed: u64, mirror_percent: f64) -> Vec<String> {
+    let (canary_addr, canary) = spawn_stub_e(Behaviour::OK).await;
+    let harness = Harness::build(
+        Behaviour::OK,
+        Behaviour::OK,
+        |fan_out| FanOutConfig {
+            service_e_canary: Some(CanaryConfig {
+                addr: canary_addr.to_string(),
+                mirror_percent,
+            }),
+            rng: shared_rng(Some(seed)),
+            ..fan_out
+        },
+        service_b_config(),
+    )
+    .await;
+
+    for i in 0..20 {
+        let mut request = Request::new(process_request("item-1"));
+        inject_request_id(request.metadata_mut(), &format!("req-{}", i));
+        harness.process(request).await;
+    }
+    // The mirrored calls run in the background, so give the last ones time to
+    // land
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut ids: Vec<String> = canary
+        .received()
+        .into_iter()
+        .map(|request| request.metadata.unwrap().request_id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn canary_sampling_follows_the_seed() {
+    let mirrored = mirrored_request_ids(3, 50.0).await;
+
+    assert!(
+        !mirrored.is_empty() && mirrored.len() < 20,
+        "{:?}",
+        mirrored
+    );
+    assert_eq!(mirrored_request_ids(3, 50.0).await, mirrored);
+}
+
+/// A processor that turns every request down as invalid
+struct RejectingProcessor;
+
+#[tonic::async_trait]
+impl Processor for RejectingProcessor {
+    async fn process(
+        &self,
+        _req: ProcessRequest,
+        _ctx: &ProcessContext,
+    ) -> Result<ProcessResponse, ProcessError> {
+        Err(ProcessError::InvalidRequest(String::from("not today")))
+    }
+}
+
+#[tokio::test]
+async fn processor_errors_become_grpc_statuses() {
+    let recent = Arc::new(RecentRequests::new(NonZeroUsize::new(8).unwrap()));
+    let config = ServiceBConfig {
+        recent_requests: Some(recent.clone()),
+        ..service_b_config()
+    };
+    let service = ServiceBImpl::new(config, Arc::new(RejectingProcessor), test_metrics());
+
+    let status = service
+        .process_data(Request::new(process_request("item-1")))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    assert_eq!(status.message(), "not today");
+    assert_eq!(recorded_statuses(&recent), ["error"]);
+}
+
+#[tokio::test]
+async fn fan_out_rejects_an_unsupported_operation() {
+    // Never connected: the request must be turned down before any call
+    let processor =
+        FanOutProcessor::new(fan_out_config("127.0.0.1:1", "127.0.0.1:1"), test_metrics()).unwrap();
+    let req = ProcessRequest {
+        operation: String::from("median"),
+        ..process_request("item-1")
+    };
+
+    let result = processor
+        .process(
+            req,
+            &ProcessContext::new("ProcessData", String::from("req-1"), None),
+        )
+        .await;
+
+    assert!(
+        matches!(result, Err(ProcessError::InvalidRequest(_))),
+        "{:?}",
+        result
+    );
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use prost::Message;
+use prost_types::FileDescriptorSet;
+
+use crate::grpcarch;
+
+/// Label for any method not served by this process
+pub const OTHER_METHOD: &str = "other";
+
+/// Names of every RPC the server exposes, read from the compiled-in proto
+/// descriptors of Service B and the health and reflection services. Metrics
+/// label methods through `label`, so a path that doesn't name one of them
+/// (e.g. a probe for an unknown service) can't add a new label value.
+pub struct KnownMethods(HashSet<String>);
+
+impl KnownMethods {
+    pub fn served() -> Self {
+        let descriptor_sets = [
+            grpcarch::FILE_DESCRIPTOR_SET,
+            tonic_health::pb::FILE_DESCRIPTOR_SET,
+            tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET,
+        ];
+        // The sets are generated at build time, so decoding can't fail in
+        // practice; were it to, every method would just be labelled "other"
+        let methods = descriptor_sets
+            .into_iter()
+            .filter_map(|bytes| FileDescriptorSet::decode(bytes).ok())
+            .flat_map(|set| set.file)
+            .flat_map(|file| file.service)
+            .flat_map(|service| service.method)
+            .filter_map(|method| method.name)
+            .collect();
+        Self(methods)
+    }
+
+    /// `method` if it is served here, `"other"` otherwise
+    pub fn label<'a>(&self, method: &'a str) -> &'a str {
+        if self.0.contains(method) {
+            method
+        } else {
+            OTHER_METHOD
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn served_methods_keep_their_name() {
+        let known = KnownMethods::served();
+        assert_eq!(known.label("ProcessData"), "ProcessData");
+        assert_eq!(known.label("ProcessDataStream"), "ProcessDataStream");
+        assert_eq!(known.label("Check"), "Check");
+    }
+
+    #[test]
+    fn unexpected_methods_collapse_to_other() {
+        let known = KnownMethods::served();
+        assert_eq!(known.label("DeleteEverything"), OTHER_METHOD);
+        assert_eq!(known.label("processdata"), OTHER_METHOD);
+        assert_eq!(known.label(""), OTHER_METHOD);
+    }
+}
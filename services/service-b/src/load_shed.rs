@@ -0,0 +1,188 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{BoxError, Layer, Service};
+use tracing::warn;
+
+use crate::priority::Priority;
+use crate::{ServiceBMetrics, SharedRng};
+
+/// Health probes must keep working while load is being shed
+const EXEMPT_PATH_PREFIX: &str = "/grpc.health.v1.Health/";
+
+/// How far one window moves the shed rate per unit of relative error, e.g.
+/// a window averaging twice the target raises the rate by this much
+const GAIN: f64 = 0.1;
+
+/// Settings for `LoadShedLayer`
+#[derive(Clone, Copy, Debug)]
+pub struct LoadShedConfig {
+    /// Average latency the controller steers towards
+    pub target: Duration,
+    /// Ceiling on the fraction of requests rejected, so some traffic always
+    /// gets through to show whether latency has recovered
+    pub max_shed_rate: f64,
+    /// How often the shed rate is recalculated from the latency seen since
+    pub window: Duration,
+}
+
+/// Rejects a fraction of requests with `RESOURCE_EXHAUSTED` while latency runs
+/// above target, to protect tail latency before a hard limit is reached.
+///
+/// At the end of every window the average latency of the requests completed
+/// during it is compared with the target, and the shed rate moved in
+/// proportion to the relative error: up while latency stays over target, back
+/// down once it recovers. A single slow request only nudges the rate, so
+/// shedding needs latency to stay high over several windows to build up. The
+/// current rate is exported as a gauge.
+///
+/// The rate is weighted by each request's `x-priority`, so low-priority
+/// requests are shed before normal ones and high-priority ones last; the
+/// ceiling applies to every priority alike.
+#[derive(Clone)]
+pub struct LoadShedLayer {
+    config: LoadShedConfig,
+    state: Arc<Mutex<Controller>>,
+    /// Picks which requests are shed at the current rate
+    rng: SharedRng,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+struct Controller {
+    window_start: Instant,
+    total_ms: f64,
+    samples: u64,
+    shed_rate: f64,
+}
+
+impl LoadShedLayer {
+    pub fn new(config: LoadShedConfig, rng: SharedRng, metrics: Arc<ServiceBMetrics>) -> Self {
+        metrics.record_load_shed_rate(0.0);
+        Self {
+            config,
+            state: Arc::new(Mutex::new(Controller {
+                window_start: Instant::now(),
+                total_ms: 0.0,
+                samples: 0,
+                shed_rate: 0.0,
+            })),
+            rng,
+            metrics,
+        }
+    }
+
+    /// The shed rate for a request arriving now, closing the window first if
+    /// it has run its course
+    fn shed_rate(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        self.roll_window(&mut state);
+        state.shed_rate
+    }
+
+    fn observe(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.total_ms += latency.as_secs_f64() * 1000.0;
+        state.samples += 1;
+        self.roll_window(&mut state);
+    }
+
+    fn roll_window(&self, state: &mut Controller) {
+        if state.window_start.elapsed() < self.config.window {
+            return;
+        }
+        let target_ms = self.config.target.as_secs_f64() * 1000.0;
+        // A window with nothing completed counts as on-target-or-better, so an
+        // idle service sheds nothing
+        let error = if state.samples == 0 {
+            -1.0
+        } else {
+            (state.total_ms / state.samples as f64 - target_ms) / target_ms
+        };
+        let shed_rate = (state.shed_rate + GAIN * error).clamp(0.0, self.config.max_shed_rate);
+        if shed_rate > 0.0 && state.shed_rate == 0.0 {
+            warn!(
+                "[Service B] Latency over the {}ms target, shedding load",
+                self.config.target.as_millis()
+            );
+        }
+        if shed_rate != state.shed_rate {
+            self.metrics.record_load_shed_rate(shed_rate);
+        }
+        *state = Controller {
+            window_start: Instant::now(),
+            total_ms: 0.0,
+            samples: 0,
+            shed_rate,
+        };
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShed {
+            inner,
+            shedder: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoadShed<S> {
+    inner: S,
+    shedder: LoadShedLayer,
+}
+
+impl<S, B, ResBody> Service<http::Request<B>> for LoadShed<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if req.uri().path().starts_with(EXEMPT_PATH_PREFIX) {
+            let response = self.inner.call(req);
+            return Box::pin(async move { response.await.map_err(Into::into) });
+        }
+
+        let priority = Priority::from_headers(req.headers());
+        let shed_rate = (self.shedder.shed_rate() * priority.shed_weight())
+            .min(self.shedder.config.max_shed_rate);
+        if shed_rate > 0.0 && self.shedder.rng.lock().unwrap().gen::<f64>() < shed_rate {
+            self.shedder.metrics.record_rejected("load_shed", priority);
+            let target = self.shedder.config.target;
+            return Box::pin(async move {
+                Err(Box::new(Status::resource_exhausted(format!(
+                    "Service B is shedding load (latency over the {}ms target)",
+                    target.as_millis()
+                ))) as BoxError)
+            });
+        }
+
+        let shedder = self.shedder.clone();
+        let start = Instant::now();
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let result = response.await;
+            // Time to the response headers, which for streaming calls is when
+            // the handler returned rather than when the stream ends
+            shedder.observe(start.elapsed());
+            result.map_err(Into::into)
+        })
+    }
+}
@@ -1,40 +1,212 @@
-use std::env;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use opentelemetry::metrics::{Counter, Histogram, Meter};
-use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::metrics::{Counter, Gauge, Histogram, Meter, UpDownCounter};
 use opentelemetry::KeyValue;
-use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
-use opentelemetry_otlp::{LogExporter, MetricExporter, SpanExporter, WithExportConfig};
-use opentelemetry_sdk::logs::LoggerProvider;
-use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
-use opentelemetry_sdk::{runtime, trace as sdktrace, Resource};
-use rand::Rng;
-use tonic::{transport::Server, Request, Response, Status};
-use tracing::{info, instrument, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::task::JoinSet;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::service::interceptor::InterceptedService;
+use tonic::{
+    codec::CompressionEncoding,
+    transport::{Channel, ClientTlsConfig, Endpoint, Server},
+    Request, Response, Status, Streaming,
+};
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+use tower::util::Either;
+use tower::Layer;
+use tracing::{debug, info, instrument, warn, Instrument};
+
+mod admin;
+mod auth;
+mod cache;
+mod circuit_breaker;
+mod concurrency;
+mod config;
+mod discovery;
+mod downstream_error;
+mod downstream_health;
+mod export_retry;
+mod failure_policy;
+mod fallback;
+mod grpc_web;
+mod idempotency;
+#[cfg(test)]
+mod integration_tests;
+mod known_methods;
+mod load_shed;
+mod log_format;
+mod middleware;
+mod panic;
+mod payload_size;
+mod priority;
+mod processor;
+mod propagation;
+mod proto_version;
+mod provenance;
+mod rate_limit;
+mod readiness;
+mod recent;
+mod redact;
+mod retry;
+mod routing;
+mod telemetry;
+mod timeout;
+mod tls;
+mod uds;
 
 pub mod grpcarch {
     tonic::include_proto!("grpcarch");
+
+    /// Encoded descriptors for the `grpcarch` package, served by reflection
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("grpcarch_descriptor");
 }
 
+use auth::ApiKeyAuth;
+use cache::{cache_key, ResponseCache};
+use circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+use config::Config;
+use discovery::{dns_balanced_channel, SystemResolver};
+use downstream_error::DownstreamError;
+use failure_policy::{DownstreamPolicies, FailurePolicy};
+use fallback::ComputeFallback;
+use idempotency::{idempotency_key, Claim, IdempotencyStore};
+use known_methods::KnownMethods;
+use middleware::MiddlewareConfig;
+use payload_size::PayloadSizeLayer;
+use priority::Priority;
+use processor::{ProcessContext, ProcessError, Processor};
+use propagation::{
+    current_trace_id, extract_trace_context, incoming_deadline, incoming_request_id,
+    inject_request_id, inject_trace_context, record_span_status,
+};
+use provenance::ServiceIdentityInterceptor;
+use rate_limit::{caller_key, caller_service, RateLimiter};
+use recent::RecentRequests;
+use redact::PayloadRedactor;
+use retry::{is_retryable, retry_async, RetryBudget, RetryPolicy};
+use routing::{hash_routed_channel, routing_key, DownstreamChannel, DownstreamRouting};
+use telemetry::{init_telemetry, ServiceIdentity};
+use timeout::HandlerTimedOut;
+
 use grpcarch::{
     service_b_server::{ServiceB, ServiceBServer},
     service_d_client::ServiceDClient,
     service_e_client::ServiceEClient,
-    ComputeRequest, DataPayload, ProcessRequest, ProcessResponse, ProcessingMetrics,
-    RequestMetadata, ResponseStatus, ValidationRequest,
+    ComputeRequest, DataPayload, DownstreamResult, ProcessRequest, ProcessResponse,
+    ProcessingMetrics, RequestMetadata, ResponseStatus, ValidationRequest,
 };
 
+/// Service E operations a caller can select through `ProcessRequest.operation`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ComputeOperation {
+    /// Used when the caller doesn't specify an operation
+    #[default]
+    Sum,
+    Mean,
+    Max,
+}
+
+impl ComputeOperation {
+    /// An empty operation selects the default
+    fn parse(operation: &str) -> Option<Self> {
+        match operation {
+            "" | "sum" => Some(ComputeOperation::Sum),
+            "mean" => Some(ComputeOperation::Mean),
+            "max" => Some(ComputeOperation::Max),
+            _ => None,
+        }
+    }
+
+    /// The name Service E knows the operation by
+    fn wire_name(self) -> &'static str {
+        match self {
+            ComputeOperation::Sum => "sum",
+            ComputeOperation::Mean => "average",
+            ComputeOperation::Max => "max",
+        }
+    }
+}
+
+/// Service E inputs used when the caller doesn't supply any
+const DEFAULT_INPUT_VALUES: [f64; 5] = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+/// Service D rules used when the caller doesn't supply any
+const DEFAULT_VALIDATION_RULES: [&str; 2] = ["required", "format"];
+
+/// Outcome of a request, exported as the `status` metric label
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestStatus {
+    /// Every downstream call succeeded
+    Ok,
+    /// Some, but not all, downstream calls failed
+    PartialFailure,
+    /// Every downstream call failed, or the request was rejected outright
+    Error,
+    /// The caller went away before the response was ready
+    Cancelled,
+    /// The handler ran past `HANDLER_TIMEOUT_MS`
+    Timeout,
+    /// Answered without calling any downstream (`DRY_RUN` or `x-dry-run`)
+    DryRun,
+}
+
+impl RequestStatus {
+    pub fn as_label(&self) -> &'static str {
+        match self {
+            RequestStatus::Ok => "ok",
+            RequestStatus::PartialFailure => "partial_failure",
+            RequestStatus::Error => "error",
+            RequestStatus::Cancelled => "cancelled",
+            RequestStatus::Timeout => "timeout",
+            RequestStatus::DryRun => "dry_run",
+        }
+    }
+}
+
 /// Metrics for Service B
 pub struct ServiceBMetrics {
     request_counter: Counter<u64>,
     latency_histogram: Histogram<f64>,
+    downstream_latency_histogram: Histogram<f64>,
+    downstream_error_counter: Counter<u64>,
+    downstream_retry_counter: Counter<u64>,
+    retry_throttled_counter: Counter<u64>,
+    circuit_state_gauge: Gauge<u64>,
+    concurrency_saturation_gauge: Gauge<f64>,
+    load_shed_rate_gauge: Gauge<f64>,
+    queue_depth_gauge: Gauge<u64>,
+    queue_wait_histogram: Histogram<f64>,
+    request_bytes_histogram: Histogram<u64>,
+    response_bytes_histogram: Histogram<u64>,
+    panic_counter: Counter<u64>,
+    cache_hit_counter: Counter<u64>,
+    hedge_counter: Counter<u64>,
+    rate_limited_counter: Counter<u64>,
+    business_failure_counter: Counter<u64>,
+    degraded_counter: Counter<u64>,
+    rejected_counter: Counter<u64>,
+    items_processed_counter: Counter<u64>,
+    inflight_counter: UpDownCounter<i64>,
+    latency_ema_gauge: Gauge<f64>,
+    /// Smoothing factor in (0, 1]; higher values weight recent requests more
+    latency_ema_alpha: f64,
+    /// `f64` bits of the current `ProcessData` latency EMA, NaN until the first
+    /// request
+    latency_ema_ms: AtomicU64,
+    known_methods: KnownMethods,
+    /// `METRIC_CONSTANT_LABELS`, appended to every request count and latency
+    constant_labels: Vec<KeyValue>,
 }
 
 impl ServiceBMetrics {
-    pub fn new(meter: Meter) -> Self {
+    pub fn new(meter: Meter, latency_ema_alpha: f64, constant_labels: Vec<KeyValue>) -> Self {
         let request_counter = meter
             .u64_counter("service_b_requests_total")
             .with_description("Total requests to Service B")
@@ -46,158 +218,1633 @@ impl ServiceBMetrics {
             .with_unit("ms")
             .build();
 
+        let downstream_latency_histogram = meter
+            .f64_histogram("service_b_downstream_duration_ms")
+            .with_description("Downstream call duration in milliseconds, including retries")
+            .with_unit("ms")
+            .build();
+
+        let downstream_error_counter = meter
+            .u64_counter("service_b_downstream_errors_total")
+            .with_description("Failed downstream calls by error kind")
+            .build();
+
+        let downstream_retry_counter = meter
+            .u64_counter("service_b_downstream_retries_total")
+            .with_description("Retried downstream call attempts")
+            .build();
+
+        let retry_throttled_counter = meter
+            .u64_counter("service_b_retries_throttled_total")
+            .with_description("Downstream retries skipped because the retry budget was exhausted")
+            .build();
+
+        let circuit_state_gauge = meter
+            .u64_gauge("service_b_circuit_state")
+            .with_description("Downstream circuit breaker state (0=closed, 1=half-open, 2=open)")
+            .build();
+
+        let concurrency_saturation_gauge = meter
+            .f64_gauge("service_b_concurrency_saturation")
+            .with_description("Fraction of the in-flight request limit in use")
+            .build();
+
+        let load_shed_rate_gauge = meter
+            .f64_gauge("service_b_load_shed_rate")
+            .with_description(
+                "Fraction of requests currently rejected by latency-based load shedding",
+            )
+            .build();
+
+        let queue_depth_gauge = meter
+            .u64_gauge("service_b_queue_depth")
+            .with_description("Requests waiting for a slot under the concurrency limit")
+            .build();
+
+        let queue_wait_histogram = meter
+            .f64_histogram("service_b_queue_duration_ms")
+            .with_description("Time spent waiting for a concurrency slot, by outcome")
+            .with_unit("ms")
+            .build();
+
+        let request_bytes_histogram = meter
+            .u64_histogram("service_b_request_bytes")
+            .with_description("Encoded request body size in bytes")
+            .with_unit("By")
+            .build();
+
+        let response_bytes_histogram = meter
+            .u64_histogram("service_b_response_bytes")
+            .with_description("Encoded response body size in bytes")
+            .with_unit("By")
+            .build();
+
+        let panic_counter = meter
+            .u64_counter("service_b_panics_total")
+            .with_description("Handler panics converted to INTERNAL errors")
+            .build();
+
+        let cache_hit_counter = meter
+            .u64_counter("service_b_cache_hits_total")
+            .with_description("Requests answered from the response cache")
+            .build();
+
+        let hedge_counter = meter
+            .u64_counter("service_b_hedged_requests_total")
+            .with_description("Hedge requests sent after a slow downstream attempt")
+            .build();
+
+        let rate_limited_counter = meter
+            .u64_counter("service_b_rate_limited_total")
+            .with_description("Requests rejected by the per-caller rate limit")
+            .build();
+
+        let business_failure_counter = meter
+            .u64_counter("service_b_validation_failures_total")
+            .with_description("Downstream responses reporting success = false")
+            .build();
+
+        let degraded_counter = meter
+            .u64_counter("service_b_degraded_total")
+            .with_description(
+                "Responses served with a fallback in place of a failed downstream result",
+            )
+            .build();
+
+        let rejected_counter = meter
+            .u64_counter("service_b_rejected_requests_total")
+            .with_description(
+                "Requests turned away by load shedding or the concurrency limit, by priority",
+            )
+            .build();
+
+        let items_processed_counter = meter
+            .u64_counter("service_b_items_processed_total")
+            .with_description("Payloads run through the pipeline, across every RPC")
+            .build();
+
+        let inflight_counter = meter
+            .i64_up_down_counter("service_b_inflight_requests")
+            .with_description("Requests currently being handled")
+            .build();
+
+        let latency_ema_gauge = meter
+            .f64_gauge("service_b_request_duration_ema_ms")
+            .with_description("Exponential moving average of ProcessData duration in milliseconds")
+            .with_unit("ms")
+            .build();
+
         Self {
             request_counter,
             latency_histogram,
+            downstream_latency_histogram,
+            downstream_error_counter,
+            downstream_retry_counter,
+            retry_throttled_counter,
+            circuit_state_gauge,
+            concurrency_saturation_gauge,
+            load_shed_rate_gauge,
+            queue_depth_gauge,
+            queue_wait_histogram,
+            request_bytes_histogram,
+            response_bytes_histogram,
+            panic_counter,
+            cache_hit_counter,
+            hedge_counter,
+            rate_limited_counter,
+            business_failure_counter,
+            degraded_counter,
+            rejected_counter,
+            items_processed_counter,
+            inflight_counter,
+            latency_ema_gauge,
+            latency_ema_alpha,
+            latency_ema_ms: AtomicU64::new(f64::NAN.to_bits()),
+            known_methods: KnownMethods::served(),
+            constant_labels,
         }
     }
 
-    pub fn record_request(&self, method: &str, status: &str) {
-        self.request_counter.add(
-            1,
+    /// `method` and `status`, followed by the constant labels
+    fn request_labels(&self, method: &str, status: RequestStatus) -> Vec<KeyValue> {
+        [
+            self.method_label(method),
+            KeyValue::new("status", status.as_label()),
+        ]
+        .into_iter()
+        .chain(self.constant_labels.iter().cloned())
+        .collect()
+    }
+
+    /// The `method` label, with anything but a served RPC bucketed into
+    /// `other` to keep the label's cardinality bounded
+    fn method_label(&self, method: &str) -> KeyValue {
+        KeyValue::new("method", self.known_methods.label(method).to_string())
+    }
+
+    pub fn record_request(&self, method: &str, status: RequestStatus) {
+        self.request_counter
+            .add(1, &self.request_labels(method, status));
+    }
+
+    pub fn record_latency(&self, method: &str, status: RequestStatus, duration_ms: f64) {
+        self.latency_histogram
+            .record(duration_ms, &self.request_labels(method, status));
+        // Dry runs skip the downstream calls, so their latency says nothing
+        // about how the service is doing
+        if method == "ProcessData" && status != RequestStatus::DryRun {
+            self.update_latency_ema(duration_ms);
+        }
+    }
+
+    fn update_latency_ema(&self, duration_ms: f64) {
+        let step = |ema: f64| {
+            if ema.is_nan() {
+                duration_ms
+            } else {
+                ema + self.latency_ema_alpha * (duration_ms - ema)
+            }
+        };
+        // The closure always returns Some, so both arms hold the previous value
+        let previous =
+            match self
+                .latency_ema_ms
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                    Some(step(f64::from_bits(bits)).to_bits())
+                }) {
+                Ok(bits) | Err(bits) => f64::from_bits(bits),
+            };
+        self.latency_ema_gauge.record(step(previous), &[]);
+    }
+
+    pub fn record_downstream(&self, downstream: &str, status: &str, duration_ms: f64) {
+        self.downstream_latency_histogram.record(
+            duration_ms,
             &[
-                KeyValue::new("method", method.to_string()),
+                KeyValue::new("downstream", downstream.to_string()),
                 KeyValue::new("status", status.to_string()),
             ],
         );
     }
 
-    pub fn record_latency(&self, method: &str, duration_ms: f64) {
-        self.latency_histogram.record(
-            duration_ms,
-            &[KeyValue::new("method", method.to_string())],
+    /// `kind` is one of "timeout", "connection", "rpc" or "circuit_open"
+    pub fn record_downstream_error(&self, downstream: &str, kind: &str) {
+        self.downstream_error_counter.add(
+            1,
+            &[
+                KeyValue::new("downstream", downstream.to_string()),
+                KeyValue::new("kind", kind.to_string()),
+            ],
+        );
+    }
+
+    pub fn record_retry(&self, downstream: &str) {
+        self.downstream_retry_counter
+            .add(1, &[KeyValue::new("downstream", downstream.to_string())]);
+    }
+
+    pub fn record_retry_throttled(&self, downstream: &str) {
+        self.retry_throttled_counter
+            .add(1, &[KeyValue::new("downstream", downstream.to_string())]);
+    }
+
+    pub fn record_circuit_state(&self, downstream: &str, state: CircuitState) {
+        self.circuit_state_gauge.record(
+            state as u64,
+            &[KeyValue::new("downstream", downstream.to_string())],
+        );
+    }
+
+    pub fn record_concurrency_saturation(&self, saturation: f64) {
+        self.concurrency_saturation_gauge.record(saturation, &[]);
+    }
+
+    pub fn record_load_shed_rate(&self, rate: f64) {
+        self.load_shed_rate_gauge.record(rate, &[]);
+    }
+
+    pub fn record_queue_depth(&self, depth: usize) {
+        self.queue_depth_gauge.record(depth as u64, &[]);
+    }
+
+    pub fn record_queue_wait(&self, outcome: &'static str, duration_ms: f64) {
+        self.queue_wait_histogram
+            .record(duration_ms, &[KeyValue::new("outcome", outcome)]);
+    }
+
+    pub fn record_request_bytes(&self, method: &str, bytes: u64) {
+        self.request_bytes_histogram
+            .record(bytes, &[self.method_label(method)]);
+    }
+
+    pub fn record_response_bytes(&self, method: &str, bytes: u64) {
+        self.response_bytes_histogram
+            .record(bytes, &[self.method_label(method)]);
+    }
+
+    pub fn record_panic(&self, method: &str) {
+        self.panic_counter.add(1, &[self.method_label(method)]);
+    }
+
+    pub fn record_cache_hit(&self, method: &str) {
+        self.cache_hit_counter.add(1, &[self.method_label(method)]);
+    }
+
+    pub fn record_hedge(&self, downstream: &str) {
+        self.hedge_counter
+            .add(1, &[KeyValue::new("downstream", downstream.to_string())]);
+    }
+
+    /// Counted separately from `record_downstream_error`: the downstream is up
+    /// and answered, it just rejected the request
+    pub fn record_business_failure(&self, downstream: &str) {
+        self.business_failure_counter
+            .add(1, &[KeyValue::new("downstream", downstream.to_string())]);
+    }
+
+    pub fn record_inflight(&self, method: &str, delta: i64) {
+        self.inflight_counter
+            .add(delta, &[self.method_label(method)]);
+    }
+
+    /// `reason` is `load_shed` or `concurrency_limit`
+    pub fn record_rejected(&self, reason: &'static str, priority: Priority) {
+        self.rejected_counter.add(
+            1,
+            &[
+                KeyValue::new("reason", reason),
+                KeyValue::new("priority", priority.as_label()),
+            ],
         );
     }
+
+    pub fn record_items_processed(&self, method: &str, items: u64) {
+        self.items_processed_counter
+            .add(items, &[self.method_label(method)]);
+    }
+
+    pub fn record_degraded(&self, downstream: &str) {
+        self.degraded_counter
+            .add(1, &[KeyValue::new("downstream", downstream.to_string())]);
+    }
+
+    /// `caller` is the label from `RateLimiter::caller_label`
+    pub fn record_rate_limited(&self, caller: &str) {
+        self.rate_limited_counter
+            .add(1, &[KeyValue::new("caller", caller.to_string())]);
+    }
 }
 
+/// A downstream channel with Service B's identity stamped on every call
+type DownstreamTransport = InterceptedService<DownstreamChannel, ServiceIdentityInterceptor>;
+
+/// The default `Processor`: calls Service E (computation) and Service D
+/// (validation) for every request, with the timeouts, retries, hedging and
+/// circuit breaking around each call
+pub struct FanOutProcessor {
+    service_d_client: ServiceDClient<DownstreamTransport>,
+    service_e_client: ServiceEClient<DownstreamTransport>,
+    /// The clients' transports, for the untyped calls made by `warmup`
+    downstream_transports: [(&'static str, DownstreamTransport); 2],
+    service_d_timeout: Duration,
+    service_e_timeout: Duration,
+    /// Set only when Service E has several endpoints to hedge across
+    service_e_hedge_delay: Option<Duration>,
+    service_e_canary: Option<CanaryMirror>,
+    retry_policy: RetryPolicy,
+    service_d_breaker: Arc<CircuitBreaker>,
+    service_e_breaker: Arc<CircuitBreaker>,
+    rng: SharedRng,
+    failure_policies: DownstreamPolicies,
+    compute_fallback: Option<ComputeFallback>,
+    processor_id: Arc<str>,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+/// The gRPC side of Service B, handing each request's actual work to its
+/// `Processor`
+#[derive(Clone)]
 pub struct ServiceBImpl {
-    service_d_addr: String,
-    service_e_addr: String,
+    processor: Arc<dyn Processor>,
+    response_cache: Option<Arc<ResponseCache>>,
+    baggage_span_attributes: Arc<[String]>,
+    processor_id: Arc<str>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Set when request and response payloads are logged
+    payload_redactor: Option<Arc<PayloadRedactor>>,
+    idempotency_store: Option<Arc<IdempotencyStore>>,
+    slow_request_threshold: Option<Duration>,
+    dry_run: bool,
+    recent_requests: Option<Arc<RecentRequests>>,
     metrics: Arc<ServiceBMetrics>,
 }
 
+/// Connection settings applied to every downstream endpoint. Keepalives stop
+/// idle connections from being silently dropped by load balancers and NAT, so
+/// the first call after a quiet period doesn't fail on a dead connection.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelTuning {
+    pub connect_timeout: Duration,
+    pub tcp_keepalive: Option<Duration>,
+    /// HTTP/2 PING interval; `None` disables HTTP/2 keepalive
+    pub http2_keepalive_interval: Option<Duration>,
+    /// How long to wait for a PING ack before closing the connection
+    pub http2_keepalive_timeout: Duration,
+    /// Also send PINGs while no calls are in flight
+    pub keepalive_while_idle: bool,
+}
+
+impl ChannelTuning {
+    pub fn apply(self, endpoint: Endpoint) -> Endpoint {
+        let endpoint = endpoint
+            .connect_timeout(self.connect_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        match self.http2_keepalive_interval {
+            Some(interval) => endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_timeout(self.http2_keepalive_timeout)
+                .keep_alive_while_idle(self.keepalive_while_idle),
+            None => endpoint,
+        }
+    }
+}
+
+/// Downstream connection and resilience settings for `FanOutProcessor`
+pub struct FanOutConfig {
+    pub service_d_addr: String,
+    pub service_e_addr: String,
+    pub service_d_timeout: Duration,
+    pub service_e_timeout: Duration,
+    /// Send a second Service E request if the first hasn't answered within
+    /// this delay, taking whichever finishes first. Every compute operation is
+    /// idempotent, so the duplicate is harmless.
+    pub service_e_hedge_delay: Option<Duration>,
+    /// Also send a share of compute calls to a canary Service E
+    pub service_e_canary: Option<CanaryConfig>,
+    pub retry_policy: RetryPolicy,
+    pub breaker_config: CircuitBreakerConfig,
+    pub client_tls: Option<ClientTlsConfig>,
+    pub channel_tuning: ChannelTuning,
+    /// Re-resolve downstream hostnames on this interval (DNS-based discovery)
+    pub dns_refresh: Option<Duration>,
+    /// How calls are spread across each downstream's replicas
+    pub routing: DownstreamRouting,
+    /// Compress requests to, and accept compressed responses from, downstreams.
+    /// gRPC has no request-side negotiation, so every downstream must support
+    /// the encoding or it will reject calls with `UNIMPLEMENTED`.
+    pub compression: Option<CompressionEncoding>,
+    /// Draws the simulated processing delay and the replica for calls without
+    /// a routing key
+    pub rng: SharedRng,
+    /// Which downstream failures fail the request
+    pub failure_policies: DownstreamPolicies,
+    /// Identifies this instance in `ProcessingMetrics.processor_id`
+    pub processor_id: String,
+    /// Answer with a stand-in Service E result instead of failing when the
+    /// compute call fails
+    pub compute_fallback: Option<ComputeFallback>,
+    /// Largest encoded message sent to a downstream, matching the limit on
+    /// what Service B accepts
+    pub max_message_bytes: usize,
+    /// Advertised to the downstreams on every call
+    pub identity: ServiceIdentity,
+}
+
+/// How `ServiceBImpl` handles requests around its `Processor`
+pub struct ServiceBConfig {
+    /// Serve repeated `ProcessData` requests from memory. Only safe when
+    /// processing is idempotent, since a hit skips the processor entirely.
+    pub response_cache: Option<ResponseCacheConfig>,
+    /// Incoming baggage keys copied onto the request span as attributes
+    pub baggage_span_attributes: Vec<String>,
+    /// Identifies this instance in `ProcessingMetrics.processor_id`
+    pub processor_id: String,
+    /// Requests per second allowed from each caller
+    pub rate_limit_rps: Option<f64>,
+    /// Callers named in the rate-limit rejection metric; the rest are
+    /// labelled `other`
+    pub rate_limit_labelled_callers: Vec<String>,
+    /// Log requests and responses, redacted and truncated by this
+    pub payload_redactor: Option<PayloadRedactor>,
+    /// Replay `ProcessData` responses to requests repeating an
+    /// `idempotency-key`
+    pub idempotency_store: Option<ResponseCacheConfig>,
+    /// Payloads taking longer than this are logged at `warn` with a per-step
+    /// breakdown; the rest only at `debug`
+    pub slow_request_threshold: Option<Duration>,
+    /// Answer every `ProcessData` request without calling the processor, as
+    /// the `x-dry-run` header does per request
+    pub dry_run: bool,
+    /// Where finished requests are recorded for the admin `/recent` endpoint
+    pub recent_requests: Option<Arc<RecentRequests>>,
+}
+
+/// Size and lifetime of the `ProcessData` response cache or idempotency store
+#[derive(Clone, Debug)]
+pub struct ResponseCacheConfig {
+    pub max_entries: NonZeroUsize,
+    pub ttl: Duration,
+}
+
+/// A Service E replica that is sent copies of live compute calls, from
+/// `SERVICE_E_CANARY_ADDR` and `E_MIRROR_PERCENT`
+#[derive(Clone, Debug)]
+pub struct CanaryConfig {
+    pub addr: String,
+    /// Share of compute calls mirrored, 0-100
+    pub mirror_percent: f64,
+}
+
+/// Label canary calls are recorded under in the downstream metrics, keeping
+/// them apart from the real Service E
+const CANARY_DOWNSTREAM: &str = "service-e-canary";
+
+#[derive(Clone)]
+struct CanaryMirror {
+    client: ServiceEClient<DownstreamTransport>,
+    mirror_percent: f64,
+}
+
+impl FanOutProcessor {
+    /// Builds the downstream channels once. Connections are established lazily on
+    /// first use and shared (multiplexed over HTTP/2) by every subsequent call.
+    pub fn new(
+        config: FanOutConfig,
+        metrics: Arc<ServiceBMetrics>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let tls = config.client_tls.as_ref();
+        let tuning = config.channel_tuning;
+        let channel = |addr: &str| -> Result<DownstreamChannel, Box<dyn std::error::Error>> {
+            Ok(match (uds::unix_path(addr), config.dns_refresh) {
+                (Some(path), _) => Either::A(uds::unix_channel(path, tuning)?),
+                (None, refresh) if config.routing == DownstreamRouting::ConsistentHash => {
+                    Either::B(hash_routed_channel(
+                        addr,
+                        tls,
+                        tuning,
+                        refresh,
+                        config.rng.clone(),
+                    )?)
+                }
+                (None, Some(refresh)) => Either::A(dns_balanced_channel(
+                    addr,
+                    tls,
+                    tuning,
+                    refresh,
+                    SystemResolver,
+                )?),
+                (None, None) => Either::A(build_channel(addr, tls, tuning)?),
+            })
+        };
+        let service_d_channel = channel(&config.service_d_addr)?;
+        let service_e_channel = channel(&config.service_e_addr)?;
+        let canary_channel = config
+            .service_e_canary
+            .as_ref()
+            .map(|canary| channel(&canary.addr))
+            .transpose()?;
+
+        // A hedge only helps if the balancer can send it to another replica:
+        // several static addresses, or DNS discovery (which may resolve many)
+        let consistent_hash = config.routing == DownstreamRouting::ConsistentHash;
+        let service_e_hedge_delay = config.service_e_hedge_delay.filter(|_| {
+            uds::unix_path(&config.service_e_addr).is_none()
+                && !consistent_hash
+                && (config.dns_refresh.is_some() || config.service_e_addr.split(',').count() > 1)
+        });
+        if config.service_e_hedge_delay.is_some() && service_e_hedge_delay.is_none() {
+            if consistent_hash {
+                warn!(
+                    "[Service B] Hedging disabled: consistent-hash routing sends \
+                     every attempt to the same replica"
+                );
+            } else {
+                warn!("[Service B] Hedging disabled: Service E has a single endpoint");
+            }
+        }
+
+        metrics.record_circuit_state("service-d", CircuitState::Closed);
+        metrics.record_circuit_state("service-e", CircuitState::Closed);
+
+        let identity = ServiceIdentityInterceptor::new(&config.identity);
+        let service_d_transport = InterceptedService::new(service_d_channel, identity.clone());
+        let service_e_transport = InterceptedService::new(service_e_channel, identity.clone());
+        let downstream_transports = [
+            ("Service D", service_d_transport.clone()),
+            ("Service E", service_e_transport.clone()),
+        ];
+        let mut service_d_client = ServiceDClient::new(service_d_transport)
+            .max_encoding_message_size(config.max_message_bytes);
+        let mut service_e_client = ServiceEClient::new(service_e_transport)
+            .max_encoding_message_size(config.max_message_bytes);
+        if let Some(encoding) = config.compression {
+            service_d_client = service_d_client
+                .send_compressed(encoding)
+                .accept_compressed(encoding);
+            service_e_client = service_e_client
+                .send_compressed(encoding)
+                .accept_compressed(encoding);
+        }
+        let service_e_canary =
+            canary_channel
+                .zip(config.service_e_canary)
+                .map(|(channel, canary)| {
+                    let mut client =
+                        ServiceEClient::new(InterceptedService::new(channel, identity))
+                            .max_encoding_message_size(config.max_message_bytes);
+                    if let Some(encoding) = config.compression {
+                        client = client.send_compressed(encoding).accept_compressed(encoding);
+                    }
+                    CanaryMirror {
+                        client,
+                        mirror_percent: canary.mirror_percent,
+                    }
+                });
+
+        Ok(Self {
+            service_d_client,
+            service_e_client,
+            downstream_transports,
+            service_d_timeout: config.service_d_timeout,
+            service_e_timeout: config.service_e_timeout,
+            service_e_hedge_delay,
+            service_e_canary,
+            retry_policy: config.retry_policy,
+            service_d_breaker: Arc::new(CircuitBreaker::new(config.breaker_config)),
+            service_e_breaker: Arc::new(CircuitBreaker::new(config.breaker_config)),
+            rng: config.rng,
+            failure_policies: config.failure_policies,
+            compute_fallback: config.compute_fallback,
+            processor_id: config.processor_id.into(),
+            metrics,
+        })
+    }
+}
+
 impl ServiceBImpl {
-    pub fn new(service_d_addr: String, service_e_addr: String, metrics: Arc<ServiceBMetrics>) -> Self {
+    pub fn new(
+        config: ServiceBConfig,
+        processor: Arc<dyn Processor>,
+        metrics: Arc<ServiceBMetrics>,
+    ) -> Self {
         Self {
-            service_d_addr,
-            service_e_addr,
+            processor,
+            response_cache: config
+                .response_cache
+                .map(|cache| Arc::new(ResponseCache::new(cache.max_entries, cache.ttl))),
+            baggage_span_attributes: config.baggage_span_attributes.into(),
+            processor_id: config.processor_id.into(),
+            rate_limiter: config
+                .rate_limit_rps
+                .map(|rps| Arc::new(RateLimiter::new(rps, config.rate_limit_labelled_callers))),
+            payload_redactor: config.payload_redactor.map(Arc::new),
+            idempotency_store: config
+                .idempotency_store
+                .map(|store| Arc::new(IdempotencyStore::new(store.max_entries, store.ttl))),
+            slow_request_threshold: config.slow_request_threshold,
+            dry_run: config.dry_run,
+            recent_requests: config.recent_requests,
             metrics,
         }
     }
 }
 
+impl FanOutProcessor {
+    /// The Service D and E breakers, in that order
+    pub fn circuit_breakers(&self) -> [Arc<CircuitBreaker>; 2] {
+        [
+            self.service_d_breaker.clone(),
+            self.service_e_breaker.clone(),
+        ]
+    }
+}
+
+/// A warm-up call is given up on after this long
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl FanOutProcessor {
+    /// Connects to Service D and E ahead of the first request, so it doesn't
+    /// pay for the TCP, TLS and HTTP/2 handshakes. Each gets a health check,
+    /// which warms the connection whether or not the downstream serves the
+    /// health service, since any gRPC answer means it is up. With several
+    /// replicas only the one the check lands on is warmed. Failures are only
+    /// logged.
+    pub async fn warmup(&self) {
+        let warm = |(name, transport): &(&'static str, DownstreamTransport)| {
+            let name = *name;
+            let mut client = HealthClient::new(transport.clone());
+            async move {
+                let start = Instant::now();
+                let check = client.check(HealthCheckRequest {
+                    service: String::new(),
+                });
+                match tokio::time::timeout(WARMUP_TIMEOUT, check).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(status)) if status.code() == tonic::Code::Unimplemented => {}
+                    Ok(Err(status)) => {
+                        warn!(
+                            "[Service B] Warm-up of {} failed: {}",
+                            name,
+                            status.message()
+                        );
+                        return;
+                    }
+                    Err(_) => {
+                        warn!(
+                            "[Service B] Warm-up of {} timed out after {}s",
+                            name,
+                            WARMUP_TIMEOUT.as_secs()
+                        );
+                        return;
+                    }
+                }
+                info!(
+                    "[Service B] Connection to {} warmed up in {}ms",
+                    name,
+                    start.elapsed().as_millis()
+                );
+            }
+        };
+        let [service_d, service_e] = &self.downstream_transports;
+        tokio::join!(warm(service_d), warm(service_e));
+    }
+}
+
+/// Channel to a comma-separated list of `host:port` addresses, over TLS when a
+/// client config is given. A single address yields a plain lazily connected
+/// channel; several are load balanced (power of two choices), with endpoints
+/// that fail to connect taken out of rotation until they recover.
+fn build_channel(
+    addrs: &str,
+    tls: Option<&ClientTlsConfig>,
+    tuning: ChannelTuning,
+) -> Result<Channel, Box<dyn std::error::Error>> {
+    let endpoints = addrs
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| build_endpoint(addr, tls, tuning))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match endpoints.len() {
+        0 => Err(format!("No downstream address in {:?}", addrs).into()),
+        1 => Ok(endpoints[0].connect_lazy()),
+        _ => Ok(Channel::balance_list(endpoints.into_iter())),
+    }
+}
+
+fn build_endpoint(
+    addr: &str,
+    tls: Option<&ClientTlsConfig>,
+    tuning: ChannelTuning,
+) -> Result<Endpoint, Box<dyn std::error::Error>> {
+    let endpoint = match tls {
+        Some(tls) => Channel::from_shared(format!("https://{}", addr))?.tls_config(tls.clone())?,
+        None => Channel::from_shared(format!("http://{}", addr))?,
+    };
+    Ok(tuning.apply(endpoint))
+}
+
 #[tonic::async_trait]
 impl ServiceB for ServiceBImpl {
-    #[instrument(skip(self, request), fields(service = "service-b"))]
+    #[instrument(
+        skip(self, request),
+        fields(
+            service = "service-b",
+            processor_id = %self.processor_id,
+            request_id = tracing::field::Empty,
+            caller_service = tracing::field::Empty,
+            priority = tracing::field::Empty,
+            payload.id = tracing::field::Empty,
+            operation = tracing::field::Empty,
+            dry_run = tracing::field::Empty,
+            otel.status_code = tracing::field::Empty,
+            otel.status_description = tracing::field::Empty
+        )
+    )]
     async fn process_data(
         &self,
-        request: Request<ProcessRequest>,
-    ) -> Result<Response<ProcessResponse>, Status> {
+        request: Request<ProcessRequest>,
+    ) -> Result<Response<ProcessResponse>, Status> {
+        let start = Instant::now();
+        let _inflight = InflightGuard::new(self.metrics.clone(), "ProcessData");
+        extract_trace_context(&request, &self.baggage_span_attributes);
+        let request_id = incoming_request_id(&request);
+        let span = tracing::Span::current();
+        span.record("request_id", request_id.as_str());
+        let caller = caller_service(&request);
+        if let Some(caller) = &caller {
+            span.record("caller_service", caller.as_str());
+        }
+        proto_version::check_peer(caller.as_deref().unwrap_or("caller"), request.metadata());
+        span.record(
+            "priority",
+            Priority::from_metadata(request.metadata()).as_label(),
+        );
+        if let Some(status) = self.rate_limited("ProcessData", &request) {
+            return Err(status);
+        }
+        let deadline = incoming_deadline(&request, start);
+        if self.deadline_expired("ProcessData", deadline, start) {
+            return Err(Status::deadline_exceeded(
+                "caller deadline already exceeded",
+            ));
+        }
+        let timed_out = request.extensions().get::<HandlerTimedOut>().cloned();
+        let idempotency_key = idempotency_key(request.metadata());
+        let dry_run = self.dry_run || dry_run_requested(request.metadata());
+        let req = request.into_inner();
+        validate_request(&req)?;
+        if let Some(payload) = &req.payload {
+            span.record("payload.id", payload.id.as_str());
+        }
+        // Validated above, so the fallback is never taken
+        span.record(
+            "operation",
+            ComputeOperation::parse(&req.operation)
+                .unwrap_or_default()
+                .wire_name(),
+        );
+        self.log_request("ProcessData", &req);
+
+        // Ahead of the cache and idempotency store, so a dry run neither reads
+        // real responses nor leaves its synthetic one behind
+        if dry_run {
+            span.record("dry_run", true);
+            let mut response = Response::new(self.dry_run_response(&req, &request_id, start));
+            inject_request_id(response.metadata_mut(), &request_id);
+            return Ok(response);
+        }
+
+        let cache_key = self.response_cache.as_ref().map(|_| cache_key(&req));
+        if let Some(response) = self.cached_response("ProcessData", &request_id, cache_key, start) {
+            let mut response = Response::new(response);
+            inject_request_id(response.metadata_mut(), &request_id);
+            return Ok(response);
+        }
+
+        // Claimed before any downstream call, so a concurrent retry waits for
+        // this request's answer instead of repeating the work
+        let idempotency_claim = match (&self.idempotency_store, idempotency_key) {
+            (Some(store), Some(key)) => match store.claim(&key, cache::cache_key(&req)).await? {
+                Claim::Replay(response) => {
+                    info!(
+                        "[Service B] ProcessData replayed for idempotency key {}",
+                        key
+                    );
+                    // Counted under the outcome being replayed, not as a success
+                    self.record_outcome(
+                        "ProcessData",
+                        &request_id,
+                        request_status(&response),
+                        start.elapsed().as_millis() as i64,
+                        Some(&response),
+                    );
+                    self.log_response("ProcessData", &response);
+                    let mut response = Response::new(*response);
+                    inject_request_id(response.metadata_mut(), &request_id);
+                    return Ok(response);
+                }
+                Claim::Owner(claim) => Some(claim),
+            },
+            _ => None,
+        };
+
+        let mut cancellation = CancellationGuard {
+            metrics: &self.metrics,
+            completed: false,
+            timed_out,
+        };
+
+        let result = self
+            .process_item("ProcessData", req, &request_id, deadline)
+            .await;
+        // A processor error is an answer too, not a cancellation
+        cancellation.completed = true;
+        let response = result?;
+        record_span_status(
+            response
+                .status
+                .as_ref()
+                .filter(|status| !status.success)
+                .map(|status| status.message.as_str()),
+        );
+
+        // Unlike the cache, partial failures are kept too: the calls that did
+        // succeed must not be repeated by a retry
+        if let Some(claim) = idempotency_claim {
+            claim.complete(&response);
+        }
+
+        // Only clean successes are cached, so a downstream outage (or a
+        // degraded answer covering for one) isn't replayed for the whole TTL
+        if let (Some(cache), Some(key)) = (&self.response_cache, cache_key) {
+            if response.downstream_results.iter().all(|r| r.success) {
+                cache.insert(key, response.clone());
+            }
+        }
+
+        let mut response = Response::new(response);
+        inject_request_id(response.metadata_mut(), &request_id);
+        Ok(response)
+    }
+
+    type ProcessDataStreamStream = ReceiverStream<Result<ProcessResponse, Status>>;
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            service = "service-b",
+            processor_id = %self.processor_id,
+            request_id = tracing::field::Empty
+        )
+    )]
+    async fn process_data_stream(
+        &self,
+        request: Request<ProcessRequest>,
+    ) -> Result<Response<Self::ProcessDataStreamStream>, Status> {
+        let start = Instant::now();
+        let inflight = InflightGuard::new(self.metrics.clone(), "ProcessDataStream");
+        extract_trace_context(&request, &self.baggage_span_attributes);
+        let request_id = incoming_request_id(&request);
+        tracing::Span::current().record("request_id", request_id.as_str());
+        if let Some(status) = self.rate_limited("ProcessDataStream", &request) {
+            return Err(status);
+        }
+        let deadline = incoming_deadline(&request, start);
+        if self.deadline_expired("ProcessDataStream", deadline, start) {
+            return Err(Status::deadline_exceeded(
+                "caller deadline already exceeded",
+            ));
+        }
+        let mut req = request.into_inner();
+        validate_operation(&req.operation)?;
+        for payload in &req.payloads {
+            validate_payload(Some(payload))?;
+        }
+        info!(
+            "[Service B] ProcessDataStream called - {} items",
+            req.payloads.len()
+        );
+        self.log_request("ProcessDataStream", &req);
+
+        // Capacity 1: the next item is only processed once the previous response
+        // has been handed to the transport, so a slow reader pauses processing
+        // instead of responses piling up in memory
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let service = self.clone();
+        let stream_request_id = request_id.clone();
+        let payloads = std::mem::take(&mut req.payloads);
+        tokio::spawn(
+            async move {
+                // Still in flight until the last item has been sent
+                let _inflight = inflight;
+                for payload in payloads {
+                    // Each item is processed as a request of its own
+                    let item = service.process_item(
+                        "ProcessDataStream",
+                        ProcessRequest {
+                            payload: Some(payload),
+                            ..req.clone()
+                        },
+                        &stream_request_id,
+                        deadline,
+                    );
+                    let result = tokio::select! {
+                        result = item => result,
+                        // The caller went away; drop the in-flight downstream calls
+                        _ = tx.closed() => return,
+                    };
+                    let failed = result.is_err();
+                    if tx.send(result).await.is_err() || failed {
+                        return;
+                    }
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+
+        let mut response = Response::new(ReceiverStream::new(rx));
+        inject_request_id(response.metadata_mut(), &request_id);
+        Ok(response)
+    }
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            service = "service-b",
+            processor_id = %self.processor_id,
+            request_id = tracing::field::Empty
+        )
+    )]
+    async fn process_data_batch(
+        &self,
+        request: Request<Streaming<ProcessRequest>>,
+    ) -> Result<Response<ProcessResponse>, Status> {
+        let start = Instant::now();
+        let _inflight = InflightGuard::new(self.metrics.clone(), "ProcessDataBatch");
+        extract_trace_context(&request, &self.baggage_span_attributes);
+        let request_id = incoming_request_id(&request);
+        tracing::Span::current().record("request_id", request_id.as_str());
+        if let Some(status) = self.rate_limited("ProcessDataBatch", &request) {
+            return Err(status);
+        }
+        let deadline = incoming_deadline(&request, start);
+        if self.deadline_expired("ProcessDataBatch", deadline, start) {
+            return Err(Status::deadline_exceeded(
+                "caller deadline already exceeded",
+            ));
+        }
+        let mut stream = request.into_inner();
+        info!("[Service B] ProcessDataBatch called");
+
+        // Items are pulled off the stream only while fewer than
+        // BATCH_PIPELINE_DEPTH are in flight, so memory stays bounded however
+        // long the stream is. Dropping the set (caller cancelled) aborts them.
+        let mut in_flight = JoinSet::new();
+        let mut batch = BatchSummary::default();
+        while let Some(req) = stream.message().await? {
+            validate_request(&req)?;
+            self.log_request("ProcessDataBatch", &req);
+            if in_flight.len() >= BATCH_PIPELINE_DEPTH {
+                if let Some(item) = in_flight.join_next().await {
+                    batch.add(&item.map_err(|e| Status::internal(e.to_string()))??);
+                }
+            }
+            let service = self.clone();
+            let request_id = request_id.clone();
+            in_flight.spawn(
+                async move {
+                    service
+                        .process_item("ProcessDataBatch", req, &request_id, deadline)
+                        .await
+                }
+                .instrument(tracing::Span::current()),
+            );
+        }
+        while let Some(item) = in_flight.join_next().await {
+            batch.add(&item.map_err(|e| Status::internal(e.to_string()))??);
+        }
+
+        let duration_ms = start.elapsed().as_millis() as i64;
+        info!(
+            "[Service B] Batch complete: {} items, {} failed (duration: {}ms)",
+            batch.items, batch.failed, duration_ms
+        );
+
+        let response = batch.into_response(duration_ms, &self.processor_id);
+        self.log_response("ProcessDataBatch", &response);
+        let mut response = Response::new(response);
+        inject_request_id(response.metadata_mut(), &request_id);
+        Ok(response)
+    }
+
+    type ProcessStreamStream = ReceiverStream<Result<ProcessResponse, Status>>;
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            service = "service-b",
+            processor_id = %self.processor_id,
+            request_id = tracing::field::Empty
+        )
+    )]
+    async fn process_stream(
+        &self,
+        request: Request<Streaming<ProcessRequest>>,
+    ) -> Result<Response<Self::ProcessStreamStream>, Status> {
+        let start = Instant::now();
+        let inflight = InflightGuard::new(self.metrics.clone(), "ProcessStream");
+        extract_trace_context(&request, &self.baggage_span_attributes);
+        let request_id = incoming_request_id(&request);
+        tracing::Span::current().record("request_id", request_id.as_str());
+        if let Some(status) = self.rate_limited("ProcessStream", &request) {
+            return Err(status);
+        }
+        let deadline = incoming_deadline(&request, start);
+        if self.deadline_expired("ProcessStream", deadline, start) {
+            return Err(Status::deadline_exceeded(
+                "caller deadline already exceeded",
+            ));
+        }
+        let mut stream = request.into_inner();
+        info!("[Service B] ProcessStream session opened");
+
+        // New requests are only read while fewer than SESSION_CONCURRENCY are
+        // in flight, so a fast sender is pushed back on rather than buffered.
+        // Once the caller half-closes, the remaining items are drained and the
+        // response stream ends; if it goes away, the set is dropped and aborts
+        // them.
+        let (tx, rx) = tokio::sync::mpsc::channel(SESSION_CONCURRENCY);
+        let service = self.clone();
+        let session_request_id = request_id.clone();
+        tokio::spawn(
+            async move {
+                // Still in flight until the session ends
+                let _inflight = inflight;
+                let mut in_flight = JoinSet::new();
+                let mut inbound_open = true;
+                let mut items = 0;
+                while inbound_open || !in_flight.is_empty() {
+                    tokio::select! {
+                        message = stream.message(),
+                            if inbound_open && in_flight.len() < SESSION_CONCURRENCY =>
+                        {
+                            let req = match message {
+                                Ok(Some(req)) => req,
+                                Ok(None) => {
+                                    inbound_open = false;
+                                    continue;
+                                }
+                                Err(status) => {
+                                    warn!("[Service B] ProcessStream inbound failed: {}", status);
+                                    let _ = tx.send(Err(status)).await;
+                                    return;
+                                }
+                            };
+                            if let Err(status) = validate_request(&req) {
+                                let _ = tx.send(Err(status)).await;
+                                return;
+                            }
+                            service.log_request("ProcessStream", &req);
+                            let service = service.clone();
+                            let request_id = session_request_id.clone();
+                            in_flight.spawn(
+                                async move {
+                                    let correlation_id = req.correlation_id.clone();
+                                    let mut response = service
+                                        .process_item("ProcessStream", req, &request_id, deadline)
+                                        .await?;
+                                    response.correlation_id = correlation_id;
+                                    Ok(response)
+                                }
+                                .instrument(tracing::Span::current()),
+                            );
+                        }
+                        Some(item) = in_flight.join_next() => {
+                            let response = match item {
+                                Ok(Ok(response)) => response,
+                                Ok(Err(status)) => {
+                                    let _ = tx.send(Err(status)).await;
+                                    return;
+                                }
+                                Err(e) => {
+                                    let _ = tx.send(Err(Status::internal(e.to_string()))).await;
+                                    return;
+                                }
+                            };
+                            items += 1;
+                            if tx.send(Ok(response)).await.is_err() {
+                                return;
+                            }
+                        }
+                        // The caller went away; drop the in-flight downstream calls
+                        _ = tx.closed() => return,
+                    }
+                }
+                info!(
+                    "[Service B] ProcessStream session closed: {} items (duration: {}ms)",
+                    items,
+                    start.elapsed().as_millis()
+                );
+            }
+            .instrument(tracing::Span::current()),
+        );
+
+        let mut response = Response::new(ReceiverStream::new(rx));
+        inject_request_id(response.metadata_mut(), &request_id);
+        Ok(response)
+    }
+}
+
+/// Trailer telling gRPC clients how long to wait before retrying
+const RETRY_PUSHBACK_HEADER: &str = "grpc-retry-pushback-ms";
+
+/// Items of a `ProcessDataBatch` stream processed concurrently
+const BATCH_PIPELINE_DEPTH: usize = 4;
+
+/// Requests of a `ProcessStream` session processed concurrently
+const SESSION_CONCURRENCY: usize = 8;
+
+/// Running aggregate of the per-item responses of a `ProcessDataBatch` call
+#[derive(Default)]
+struct BatchSummary {
+    items: i32,
+    failed: i32,
+    error_code: i32,
+}
+
+impl BatchSummary {
+    fn add(&mut self, response: &ProcessResponse) {
+        self.items += 1;
+        if let Some(status) = response.status.as_ref().filter(|status| !status.success) {
+            self.failed += 1;
+            self.error_code = self.error_code.max(status.error_code);
+        }
+    }
+
+    fn into_response(self, duration_ms: i64, processor_id: &str) -> ProcessResponse {
+        let message = if self.failed == 0 {
+            format!("Processed {} items successfully", self.items)
+        } else {
+            format!(
+                "Partial failure: {} of {} items failed",
+                self.failed, self.items
+            )
+        };
+        ProcessResponse {
+            status: Some(ResponseStatus {
+                success: self.failed == 0,
+                message,
+                error_code: self.error_code,
+            }),
+            result: Some(DataPayload {
+                id: String::from("processed-batch"),
+                content: format!("Processed {} items", self.items),
+                attributes: HashMap::new(),
+                typed_attributes: HashMap::new(),
+            }),
+            metrics: Some(ProcessingMetrics {
+                processing_time_ms: duration_ms,
+                items_processed: self.items,
+                processor_id: processor_id.to_string(),
+            }),
+            output_values: Vec::new(),
+            downstream_results: Vec::new(),
+            correlation_id: String::new(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Processor for FanOutProcessor {
+    /// Runs the payload through the pipeline (E and D in parallel). Downstream
+    /// failures are reported in the response status rather than failing the
+    /// call.
+    async fn process(
+        &self,
+        req: ProcessRequest,
+        ctx: &ProcessContext,
+    ) -> Result<ProcessResponse, ProcessError> {
+        let start = Instant::now();
+        let req = &req;
+        let payload = req.payload.as_ref();
+        let request_id = ctx.request_id.as_str();
+        let deadline = ctx.deadline;
+        let data_id = payload.map(|p| p.id.clone()).unwrap_or_default();
+        let operation = ComputeOperation::parse(&req.operation).ok_or_else(|| {
+            ProcessError::InvalidRequest(format!("unsupported operation {:?}", req.operation))
+        })?;
+
+        // Simulate processing delay (10-20ms), in its own span so the trace
+        // shows local work apart from the downstream calls
+        let delay_ms = self.rng.lock().unwrap().gen_range(10..=20);
+        tokio::time::sleep(Duration::from_millis(delay_ms))
+            .instrument(tracing::info_span!("local.process", delay_ms))
+            .await;
+
+        // Call Service E (computation) and Service D (validation) concurrently;
+        // neither depends on the other's result. The calls run inside this
+        // future rather than being spawned, so when it is dropped because the
+        // caller cancelled, the in-flight downstream RPCs are dropped (and reset)
+        // with it. Likewise, whichever call is still going (retries and hedges
+        // included) when the caller's budget runs out is dropped and reported as
+        // timed out, while the other keeps its result.
+        let fan_out_deadline = deadline.map(|deadline| {
+            let now = Instant::now();
+            now + deadline
+                .saturating_duration_since(now)
+                .mul_f64(DOWNSTREAM_DEADLINE_SHARE)
+        });
+        let ((compute_result, compute_duration), (validation_result, validation_duration)) = tokio::join!(
+            self.timed(
+                "service-e",
+                self.within_deadline(
+                    "service-e",
+                    fan_out_deadline,
+                    self.call_service_e(req, operation, payload, request_id, deadline)
+                )
+            ),
+            self.timed(
+                "service-d",
+                self.within_deadline(
+                    "service-d",
+                    fan_out_deadline,
+                    self.call_service_d(req, payload, request_id, deadline)
+                )
+            )
+        );
+
+        let duration_ms = start.elapsed().as_millis() as i64;
+        let downstream_results = vec![
+            downstream_result("service-e", &compute_result, compute_duration),
+            downstream_result("service-d", &validation_result, validation_duration),
+        ];
+        let fallback = self.compute_fallback.as_ref();
+        let (output_values, compute_error, degraded) = match compute_result {
+            Ok(output_values) => {
+                if let Some(fallback) = fallback {
+                    fallback.remember(req, &output_values);
+                }
+                (output_values, None, false)
+            }
+            Err(e) => match fallback.and_then(|fallback| fallback.fallback(req)) {
+                Some(output_values) => {
+                    warn!(
+                        "[Service B] Service E failed ({}), serving fallback result",
+                        e
+                    );
+                    self.metrics.record_degraded("service-e");
+                    (output_values, None, true)
+                }
+                None => (Vec::new(), Some(e), false),
+            },
+        };
+
+        // Handle errors from downstream services
+        let mut required_failures = Vec::new();
+        let failures = [
+            ("Service E", self.failure_policies.service_e, compute_error),
+            (
+                "Service D",
+                self.failure_policies.service_d,
+                validation_result.err(),
+            ),
+        ];
+        for (downstream, policy, error) in failures {
+            let Some(e) = error else { continue };
+            match policy {
+                FailurePolicy::Required => required_failures.push((downstream, e)),
+                // Already counted in the downstream error metrics
+                FailurePolicy::Optional => warn!(
+                    "[Service B] Ignoring failure from optional {}: {}",
+                    downstream, e
+                ),
+            }
+        }
+
+        let (status, request_status) = build_status(&required_failures, degraded);
+        ctx.report_status(request_status);
+        Ok(ProcessResponse {
+            status: Some(status),
+            result: Some(DataPayload {
+                id: format!("processed-{}", data_id),
+                content: String::from("Processed data"),
+                attributes: result_attributes(payload),
+                typed_attributes: payload
+                    .map(|p| p.typed_attributes.clone())
+                    .unwrap_or_default(),
+            }),
+            metrics: Some(ProcessingMetrics {
+                processing_time_ms: duration_ms,
+                // One call handles one payload; multi-item RPCs add up their
+                // calls
+                items_processed: i32::from(payload.is_some()),
+                processor_id: self.processor_id.to_string(),
+            }),
+            output_values,
+            downstream_results,
+            // Set by ProcessStream, the only caller that matches responses up
+            correlation_id: String::new(),
+        })
+    }
+}
+
+impl ServiceBImpl {
+    /// Runs one payload through the processor and records its metrics under
+    /// `method`
+    async fn process_item(
+        &self,
+        method: &'static str,
+        req: ProcessRequest,
+        request_id: &str,
+        deadline: Option<Instant>,
+    ) -> Result<ProcessResponse, Status> {
         let start = Instant::now();
-        let req = request.into_inner();
-
         let data_id = req
             .payload
             .as_ref()
             .map(|p| p.id.clone())
             .unwrap_or_default();
-        info!("[Service B] ProcessData called - data_id: {}", data_id);
+        info!("[Service B] {} called - data_id: {}", method, data_id);
+
+        let ctx = ProcessContext::new(method, request_id.to_string(), deadline);
+        let result = self.processor.process(req, &ctx).await;
+        let duration_ms = start.elapsed().as_millis() as i64;
+
+        let request_status = match &result {
+            Ok(response) => ctx
+                .reported_status()
+                .unwrap_or_else(|| request_status(response)),
+            Err(_) => RequestStatus::Error,
+        };
+        self.record_outcome(
+            method,
+            request_id,
+            request_status,
+            duration_ms,
+            result.as_ref().ok(),
+        );
+        let response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                warn!("[Service B] {} failed: {}", method, error);
+                return Err(error.into());
+            }
+        };
+        if let Some(status) = response.status.as_ref().filter(|status| !status.success) {
+            warn!("[Service B] {}", status.message);
+        }
+
+        let slow = self
+            .slow_request_threshold
+            .is_some_and(|threshold| duration_ms as u128 > threshold.as_millis());
+        if slow {
+            let breakdown: Vec<String> = response
+                .downstream_results
+                .iter()
+                .map(|result| format!(", {}: {}ms", result.name, result.duration_ms))
+                .collect();
+            warn!(
+                "[Service B] Slow {} request {} (total: {}ms{})",
+                method,
+                request_id,
+                duration_ms,
+                breakdown.concat()
+            );
+        } else {
+            debug!(
+                "[Service B] Processing complete (duration: {}ms)",
+                duration_ms
+            );
+        }
+        self.log_response(method, &response);
 
-        // Simulate processing delay (10-20ms)
-        let delay_ms = rand::thread_rng().gen_range(10..=20);
-        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        Ok(response)
+    }
+
+    /// Logs the redacted request when `LOG_PAYLOADS` is enabled
+    fn log_request(&self, method: &str, req: &ProcessRequest) {
+        if let Some(redactor) = &self.payload_redactor {
+            info!(request = ?redactor.request(req), "[Service B] {} request", method);
+        }
+    }
 
-        // Call Service E first (computation)
-        let compute_result = self.call_service_e(&req).await;
+    /// Records a finished request in the request, latency and items-processed
+    /// metrics and the recent-requests buffer. Cache hits, replays and dry runs
+    /// count their items too, so the counter tracks items answered rather than
+    /// items sent downstream.
+    fn record_outcome(
+        &self,
+        method: &'static str,
+        request_id: &str,
+        status: RequestStatus,
+        duration_ms: i64,
+        response: Option<&ProcessResponse>,
+    ) {
+        let items_processed = response
+            .and_then(|response| response.metrics.as_ref())
+            .map_or(0, |metrics| metrics.items_processed);
+        self.metrics
+            .record_items_processed(method, items_processed as u64);
+        self.metrics.record_request(method, status);
+        self.metrics
+            .record_latency(method, status, duration_ms as f64);
+        if let Some(recent) = &self.recent_requests {
+            recent.record(request_id, current_trace_id(), method, duration_ms, status);
+        }
+    }
 
-        // Then call Service D (validation)
-        let validation_result = self.call_service_d(&req).await;
+    /// Logs the redacted response when `LOG_PAYLOADS` is enabled
+    fn log_response(&self, method: &str, response: &ProcessResponse) {
+        if let Some(redactor) = &self.payload_redactor {
+            info!(response = ?redactor.response(response), "[Service B] {} response", method);
+        }
+    }
 
+    /// Successful response to a dry-run request, shaped like a real one but
+    /// with no downstream results or output values. Recorded under the
+    /// `dry_run` status so it stays out of the real request metrics.
+    fn dry_run_response(
+        &self,
+        req: &ProcessRequest,
+        request_id: &str,
+        start: Instant,
+    ) -> ProcessResponse {
+        let payload = req.payload.as_ref();
+        let data_id = payload.map(|p| p.id.as_str()).unwrap_or_default();
+        info!("[Service B] ProcessData dry run - data_id: {}", data_id);
         let duration_ms = start.elapsed().as_millis() as i64;
 
-        // Build response
-        let mut response = ProcessResponse {
+        let response = ProcessResponse {
             status: Some(ResponseStatus {
                 success: true,
-                message: String::new(),
+                message: String::from("Dry run: downstream calls skipped"),
                 error_code: 0,
             }),
             result: Some(DataPayload {
                 id: format!("processed-{}", data_id),
                 content: String::from("Processed data"),
-                attributes: std::collections::HashMap::new(),
+                attributes: result_attributes(payload),
+                typed_attributes: payload
+                    .map(|p| p.typed_attributes.clone())
+                    .unwrap_or_default(),
             }),
             metrics: Some(ProcessingMetrics {
                 processing_time_ms: duration_ms,
-                items_processed: 1,
-                processor_id: String::from("service-b-processor"),
+                items_processed: i32::from(payload.is_some()),
+                processor_id: self.processor_id.to_string(),
             }),
+            output_values: Vec::new(),
+            downstream_results: Vec::new(),
+            correlation_id: String::new(),
         };
+        self.record_outcome(
+            "ProcessData",
+            request_id,
+            RequestStatus::DryRun,
+            duration_ms,
+            Some(&response),
+        );
+        self.log_response("ProcessData", &response);
+        response
+    }
 
-        // Handle errors from downstream services
-        let mut errors = Vec::new();
-        if let Err(e) = compute_result {
-            errors.push(format!("Service E: {}", e));
-        }
-        if let Err(e) = validation_result {
-            errors.push(format!("Service D: {}", e));
-        }
-
-        if !errors.is_empty() {
-            let error_msg = errors.join("; ");
-            warn!("[Service B] Downstream errors: {}", error_msg);
-            self.metrics.record_request("ProcessData", "error");
-            self.metrics.record_latency("ProcessData", duration_ms as f64);
-            if let Some(status) = response.status.as_mut() {
-                status.success = false;
-                status.message = format!("Partial failure: {}", error_msg);
-            }
-        } else {
-            self.metrics.record_request("ProcessData", "ok");
-            self.metrics.record_latency("ProcessData", duration_ms as f64);
-            if let Some(status) = response.status.as_mut() {
-                status.message = String::from("Processing completed successfully");
-            }
-        }
-
-        info!(
-            "[Service B] Processing complete (duration: {}ms)",
-            duration_ms
+    /// The cached response for `key`, if any, counted as a successful request
+    fn cached_response(
+        &self,
+        method: &'static str,
+        request_id: &str,
+        key: Option<u64>,
+        start: Instant,
+    ) -> Option<ProcessResponse> {
+        let response = self.response_cache.as_ref()?.get(key?)?;
+        info!("[Service B] {} served from cache", method);
+        self.metrics.record_cache_hit(method);
+        self.record_outcome(
+            method,
+            request_id,
+            RequestStatus::Ok,
+            start.elapsed().as_millis() as i64,
+            Some(&response),
         );
+        self.log_response(method, &response);
+        Some(response)
+    }
+
+    /// The `RESOURCE_EXHAUSTED` status to reject the request with if its caller
+    /// is over the rate limit, carrying a `grpc-retry-pushback-ms` hint
+    fn rate_limited<T>(&self, method: &str, request: &Request<T>) -> Option<Status> {
+        let limiter = self.rate_limiter.as_ref()?;
+        let caller = caller_key(request);
+        let retry_after = limiter.try_acquire(&caller).err()?;
+        warn!("[Service B] Rate limit exceeded for caller {}", caller);
+        self.metrics
+            .record_rate_limited(limiter.caller_label(&caller));
+        self.metrics.record_request(method, RequestStatus::Error);
+
+        let retry_after_ms = retry_after.as_millis().max(1);
+        let mut status = Status::resource_exhausted(format!(
+            "rate limit exceeded, retry after {}ms",
+            retry_after_ms
+        ));
+        if let Ok(value) = retry_after_ms.to_string().parse() {
+            status.metadata_mut().insert(RETRY_PUSHBACK_HEADER, value);
+        }
+        Some(status)
+    }
 
-        Ok(Response::new(response))
+    /// Whether the caller's deadline had already passed on arrival, in which
+    /// case the call is rejected without doing any work
+    fn deadline_expired(&self, method: &str, deadline: Option<Instant>, now: Instant) -> bool {
+        let expired = deadline.is_some_and(|deadline| deadline <= now);
+        if expired {
+            warn!("[Service B] Caller deadline already exceeded, skipping request");
+            self.metrics.record_request(method, RequestStatus::Error);
+        }
+        expired
     }
 }
 
-impl ServiceBImpl {
-    #[instrument(skip(self, _req), fields(downstream = "service-e"))]
-    async fn call_service_e(&self, _req: &ProcessRequest) -> Result<(), String> {
-        info!("[Service B] Calling Service E for computation...");
+impl FanOutProcessor {
+    #[instrument(
+        skip(self, req, payload, request_id, deadline),
+        fields(
+            downstream = "service-e",
+            otel.status_code = tracing::field::Empty,
+            otel.status_description = tracing::field::Empty
+        )
+    )]
+    async fn call_service_e(
+        &self,
+        req: &ProcessRequest,
+        operation: ComputeOperation,
+        payload: Option<&DataPayload>,
+        request_id: &str,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<f64>, DownstreamError> {
+        let result = self
+            .compute(req, operation, payload, request_id, deadline)
+            .await;
+        record_span_status(result.as_ref().err().map(|e| e.to_string()).as_deref());
+        result
+    }
+
+    /// The body of `call_service_e`, run inside its span
+    async fn compute(
+        &self,
+        req: &ProcessRequest,
+        operation: ComputeOperation,
+        payload: Option<&DataPayload>,
+        request_id: &str,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<f64>, DownstreamError> {
+        if !self.service_e_breaker.try_acquire() {
+            let error = DownstreamError::CircuitOpen;
+            self.metrics
+                .record_downstream_error("service-e", error.kind());
+            self.metrics
+                .record_circuit_state("service-e", self.service_e_breaker.state());
+            return Err(error);
+        }
 
-        let mut client = ServiceEClient::connect(format!("http://{}", self.service_e_addr))
-            .await
-            .map_err(|e| format!("Failed to connect to Service E: {}", e))?;
+        info!("[Service B] Calling Service E for computation...");
 
         let compute_request = ComputeRequest {
-            metadata: Some(RequestMetadata {
-                request_id: String::new(),
-                trace_id: String::new(),
-                caller_service: String::from("service-b"),
-                timestamp_ms: chrono_timestamp_ms(),
-            }),
-            input_values: vec![1.0, 2.0, 3.0, 4.0, 5.0],
-            operation: String::from("sum"),
+            metadata: Some(downstream_metadata(request_id, payload)),
+            input_values: if req.input_values.is_empty() {
+                DEFAULT_INPUT_VALUES.to_vec()
+            } else {
+                req.input_values.clone()
+            },
+            operation: operation.wire_name().to_string(),
         };
+        self.mirror_to_canary(&compute_request, request_id);
 
-        let response = client
-            .compute(Request::new(compute_request))
-            .await
-            .map_err(|e| format!("Service E call failed: {}", e))?;
+        let compute_request = &compute_request;
+        let result = retry_async(&self.retry_policy, "service-e", &self.metrics, || {
+            self.hedged("service-e", self.service_e_hedge_delay, move || {
+                let mut client = self.service_e_client.clone();
+                let mut request = Request::new(compute_request.clone());
+                if let Some(key) = routing_key(payload) {
+                    request.extensions_mut().insert(key);
+                }
+                inject_trace_context(&mut request);
+                inject_request_id(request.metadata_mut(), request_id);
+                let timeout = downstream_timeout(self.service_e_timeout, deadline);
+                request.set_timeout(timeout);
+                with_timeout(timeout, async move { Ok(client.compute(request).await?) })
+            })
+        })
+        .await;
+        self.record_breaker_outcome("service-e", &self.service_e_breaker, &result);
+        let response = result.map_err(|e| self.downstream_failure("service-e", e))?;
+        proto_version::check_peer("service-e", response.metadata());
 
         let resp = response.into_inner();
         if let Some(status) = resp.status {
             if !status.success {
-                return Err(format!("Service E returned failure: {}", status.message));
+                return Err(self.business_failure("service-e", status.message));
             }
         }
 
@@ -205,151 +1852,1114 @@ impl ServiceBImpl {
             "[Service B] Service E computation successful, results: {:?}",
             resp.output_values
         );
-        Ok(())
+        Ok(resp.output_values)
     }
 
-    #[instrument(skip(self, req), fields(downstream = "service-d"))]
-    async fn call_service_d(&self, req: &ProcessRequest) -> Result<(), String> {
-        info!("[Service B] Calling Service D for validation...");
+    #[instrument(
+        skip(self, req, payload, request_id, deadline),
+        fields(
+            downstream = "service-d",
+            otel.status_code = tracing::field::Empty,
+            otel.status_description = tracing::field::Empty
+        )
+    )]
+    async fn call_service_d(
+        &self,
+        req: &ProcessRequest,
+        payload: Option<&DataPayload>,
+        request_id: &str,
+        deadline: Option<Instant>,
+    ) -> Result<(), DownstreamError> {
+        let result = self.validate(req, payload, request_id, deadline).await;
+        record_span_status(result.as_ref().err().map(|e| e.to_string()).as_deref());
+        result
+    }
 
-        let mut client = ServiceDClient::connect(format!("http://{}", self.service_d_addr))
-            .await
-            .map_err(|e| format!("Failed to connect to Service D: {}", e))?;
+    /// The body of `call_service_d`, run inside its span
+    async fn validate(
+        &self,
+        req: &ProcessRequest,
+        payload: Option<&DataPayload>,
+        request_id: &str,
+        deadline: Option<Instant>,
+    ) -> Result<(), DownstreamError> {
+        if !self.service_d_breaker.try_acquire() {
+            let error = DownstreamError::CircuitOpen;
+            self.metrics
+                .record_downstream_error("service-d", error.kind());
+            self.metrics
+                .record_circuit_state("service-d", self.service_d_breaker.state());
+            return Err(error);
+        }
+
+        info!("[Service B] Calling Service D for validation...");
 
         let validation_request = ValidationRequest {
-            metadata: Some(RequestMetadata {
-                request_id: String::new(),
-                trace_id: String::new(),
-                caller_service: String::from("service-b"),
-                timestamp_ms: chrono_timestamp_ms(),
-            }),
-            data: req.payload.clone(),
-            validation_rules: vec![String::from("required"), String::from("format")],
+            metadata: Some(downstream_metadata(request_id, payload)),
+            data: payload.cloned(),
+            validation_rules: if req.validation_rules.is_empty() {
+                DEFAULT_VALIDATION_RULES.map(String::from).to_vec()
+            } else {
+                req.validation_rules.clone()
+            },
         };
 
-        let response = client
-            .validate_data(Request::new(validation_request))
-            .await
-            .map_err(|e| format!("Service D call failed: {}", e))?;
+        let result = retry_async(&self.retry_policy, "service-d", &self.metrics, || {
+            let mut client = self.service_d_client.clone();
+            let mut request = Request::new(validation_request.clone());
+            if let Some(key) = routing_key(payload) {
+                request.extensions_mut().insert(key);
+            }
+            inject_trace_context(&mut request);
+            inject_request_id(request.metadata_mut(), request_id);
+            let timeout = downstream_timeout(self.service_d_timeout, deadline);
+            request.set_timeout(timeout);
+            with_timeout(
+                timeout,
+                async move { Ok(client.validate_data(request).await?) },
+            )
+        })
+        .await;
+        self.record_breaker_outcome("service-d", &self.service_d_breaker, &result);
+        let response = result.map_err(|e| self.downstream_failure("service-d", e))?;
+        proto_version::check_peer("service-d", response.metadata());
 
         let resp = response.into_inner();
         if let Some(status) = resp.status {
             if !status.success {
-                return Err(format!("Service D returned failure: {}", status.message));
+                return Err(self.business_failure("service-d", status.message));
             }
         }
 
         info!("[Service B] Service D validation successful");
         Ok(())
     }
+
+    /// Sends a copy of a compute call to the canary Service E, for the
+    /// configured share of calls. It runs in the background with no retries
+    /// or hedging, so it adds nothing to the real call's latency; its result is
+    /// only recorded, under `CANARY_DOWNSTREAM`, and traced in a span of its own
+    /// under the request's.
+    fn mirror_to_canary(&self, compute_request: &ComputeRequest, request_id: &str) {
+        let Some(canary) = &self.service_e_canary else {
+            return;
+        };
+        if self.rng.lock().unwrap().gen::<f64>() * 100.0 >= canary.mirror_percent {
+            return;
+        }
+
+        let mut client = canary.client.clone();
+        let mut request = Request::new(compute_request.clone());
+        let span = tracing::info_span!("canary.compute", downstream = CANARY_DOWNSTREAM);
+        span.in_scope(|| inject_trace_context(&mut request));
+        inject_request_id(request.metadata_mut(), request_id);
+        let timeout = self.service_e_timeout;
+        request.set_timeout(timeout);
+        let metrics = self.metrics.clone();
+        let mirror = async move {
+            let start = Instant::now();
+            let result =
+                with_timeout(timeout, async move { Ok(client.compute(request).await?) }).await;
+            let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+            match result {
+                Ok(response) => {
+                    metrics.record_downstream(CANARY_DOWNSTREAM, "ok", elapsed_ms);
+                    if response
+                        .get_ref()
+                        .status
+                        .as_ref()
+                        .is_some_and(|s| !s.success)
+                    {
+                        metrics.record_business_failure(CANARY_DOWNSTREAM);
+                    }
+                }
+                Err(error) => {
+                    debug!("[Service B] Canary Service E call failed: {}", error);
+                    metrics.record_downstream(CANARY_DOWNSTREAM, "error", elapsed_ms);
+                    metrics.record_downstream_error(CANARY_DOWNSTREAM, error.kind());
+                }
+            }
+        };
+        tokio::spawn(mirror.instrument(span));
+    }
+
+    /// Runs a downstream call and records its latency and outcome, returning the
+    /// latency alongside the result
+    async fn timed<T>(
+        &self,
+        downstream: &str,
+        call: impl std::future::Future<Output = Result<T, DownstreamError>>,
+    ) -> (Result<T, DownstreamError>, Duration) {
+        let start = Instant::now();
+        let result = call.await;
+        let elapsed = start.elapsed();
+        let status = if result.is_ok() { "ok" } else { "error" };
+        self.metrics
+            .record_downstream(downstream, status, elapsed.as_secs_f64() * 1000.0);
+        (result, elapsed)
+    }
+
+    /// Runs a downstream call, abandoning it with a `DeadlineExceeded` timeout
+    /// if it hasn't finished by `deadline`. Dropping the call cancels its RPC.
+    async fn within_deadline<T>(
+        &self,
+        downstream: &str,
+        deadline: Option<Instant>,
+        call: impl std::future::Future<Output = Result<T, DownstreamError>>,
+    ) -> Result<T, DownstreamError> {
+        let Some(deadline) = deadline else {
+            return call.await;
+        };
+        match tokio::time::timeout_at(deadline.into(), call).await {
+            Ok(result) => result,
+            Err(_) => {
+                let error = DownstreamError::Timeout(Status::deadline_exceeded(
+                    "caller deadline reached before the downstream answered",
+                ));
+                warn!("[Service B] Gave up on {}: {}", downstream, error);
+                self.metrics
+                    .record_downstream_error(downstream, error.kind());
+                Err(error)
+            }
+        }
+    }
+
+    /// Runs `attempt`, starting a second identical attempt if the first is still
+    /// pending after `delay`, and returns whichever finishes first. The other is
+    /// dropped, cancelling its RPC. `None` runs a single attempt.
+    async fn hedged<T, Fut>(
+        &self,
+        downstream: &str,
+        delay: Option<Duration>,
+        attempt: impl Fn() -> Fut,
+    ) -> Result<T, DownstreamError>
+    where
+        Fut: std::future::Future<Output = Result<T, DownstreamError>>,
+    {
+        let first = attempt();
+        let Some(delay) = delay else {
+            return first.await;
+        };
+        tokio::pin!(first);
+        tokio::select! {
+            result = &mut first => return result,
+            _ = tokio::time::sleep(delay) => {}
+        }
+
+        info!(
+            "[Service B] No response from {} after {}ms, sending hedge request",
+            downstream,
+            delay.as_millis()
+        );
+        self.metrics.record_hedge(downstream);
+        let second = attempt();
+        tokio::select! {
+            result = first => result,
+            result = second => result,
+        }
+    }
+
+    /// Feeds the outcome of a downstream RPC into its breaker. Only transient
+    /// failures count against the breaker; any other response proves the
+    /// downstream is reachable.
+    fn record_breaker_outcome<T>(
+        &self,
+        downstream: &str,
+        breaker: &CircuitBreaker,
+        result: &Result<T, DownstreamError>,
+    ) {
+        match result {
+            Err(error) if is_retryable(error) => breaker.record_failure(),
+            _ => breaker.record_success(),
+        }
+        self.metrics
+            .record_circuit_state(downstream, breaker.state());
+    }
+
+    /// Records a `success = false` response as a span event on the current
+    /// (downstream call) span and in the business failure counter
+    fn business_failure(&self, downstream: &str, message: String) -> DownstreamError {
+        warn!(
+            downstream = downstream,
+            downstream.message = message.as_str(),
+            "downstream.business_failure"
+        );
+        self.metrics.record_business_failure(downstream);
+        DownstreamError::Rejected { message }
+    }
+
+    /// Records a failed downstream RPC (after retries) in the error counter
+    fn downstream_failure(&self, downstream: &str, error: DownstreamError) -> DownstreamError {
+        self.metrics
+            .record_downstream_error(downstream, error.kind());
+        error
+    }
 }
 
-fn chrono_timestamp_ms() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as i64
+/// Per-downstream detail for `ProcessResponse.downstream_results`
+fn downstream_result<T>(
+    name: &str,
+    result: &Result<T, DownstreamError>,
+    duration: Duration,
+) -> DownstreamResult {
+    let (error_code, message) = match result {
+        Ok(_) => (0, String::new()),
+        Err(e) => (code_for(e.code()), e.to_string()),
+    };
+    DownstreamResult {
+        name: name.to_string(),
+        success: result.is_ok(),
+        error_code,
+        message,
+        duration_ms: duration.as_millis() as i64,
+    }
 }
 
-fn init_telemetry() {
-    let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
-        .unwrap_or_else(|_| "http://localhost:4317".into());
-    let service_name = env::var("OTEL_SERVICE_NAME")
-        .unwrap_or_else(|_| "service-b".into());
+/// The response status and metric label for a request, given the failures of
+/// the downstreams required to succeed (optional ones are left out by the
+/// caller). `degraded` marks a Service E result served from the fallback.
+///
+/// With two downstreams, one failure is a partial failure and both is a total
+/// failure. The message names every failure, and `error_code` is the most
+/// severe of their codes.
+fn build_status(
+    required_failures: &[(&str, DownstreamError)],
+    degraded: bool,
+) -> (ResponseStatus, RequestStatus) {
+    let request_status = match required_failures.len() {
+        0 => RequestStatus::Ok,
+        1 => RequestStatus::PartialFailure,
+        _ => RequestStatus::Error,
+    };
+    let errors: Vec<String> = required_failures
+        .iter()
+        .map(|(downstream, e)| format!("{}: {}", downstream, e))
+        .collect();
+    let mut message = match request_status {
+        RequestStatus::Ok => String::from("Processing completed successfully"),
+        RequestStatus::PartialFailure => format!("Partial failure: {}", errors.join("; ")),
+        _ => format!("Processing failed: {}", errors.join("; ")),
+    };
+    if degraded {
+        message.push_str(" (degraded: Service E result from fallback)");
+    }
+
+    let status = ResponseStatus {
+        success: required_failures.is_empty(),
+        message,
+        error_code: required_failures
+            .iter()
+            .map(|(_, e)| code_for(e.code()))
+            .max()
+            .unwrap_or(0),
+    };
+    (status, request_status)
+}
 
-    let resource = Resource::new(vec![
-        KeyValue::new("service.name", service_name.clone()),
-        KeyValue::new("service.version", "1.0.0"),
-        KeyValue::new("deployment.environment", "development"),
-    ]);
+/// The metric label for a response that didn't come with one, such as a
+/// replay or the answer of a processor that doesn't report it: a failed
+/// request is a partial failure if some of its downstream calls still
+/// succeeded
+fn request_status(response: &ProcessResponse) -> RequestStatus {
+    match &response.status {
+        Some(status) if !status.success => {
+            if response
+                .downstream_results
+                .iter()
+                .any(|result| result.success)
+            {
+                RequestStatus::PartialFailure
+            } else {
+                RequestStatus::Error
+            }
+        }
+        _ => RequestStatus::Ok,
+    }
+}
 
-    // Initialize tracer
-    let span_exporter = SpanExporter::builder()
-        .with_tonic()
-        .with_endpoint(&otlp_endpoint)
-        .build()
-        .expect("Failed to create span exporter");
+/// Stable, HTTP-style `ResponseStatus.error_code` for a downstream gRPC status.
+/// When several downstreams fail, the highest (most severe) code is reported.
+///
+/// | gRPC code                                           | error_code |
+/// |-----------------------------------------------------|------------|
+/// | OK                                                  | 0          |
+/// | InvalidArgument, FailedPrecondition, OutOfRange     | 400        |
+/// | Unauthenticated                                     | 401        |
+/// | PermissionDenied                                    | 403        |
+/// | NotFound                                            | 404        |
+/// | AlreadyExists, Aborted                              | 409        |
+/// | ResourceExhausted                                   | 429        |
+/// | Cancelled                                           | 499        |
+/// | Unknown, Internal, DataLoss                         | 500        |
+/// | Unimplemented                                       | 501        |
+/// | Unavailable                                         | 503        |
+/// | DeadlineExceeded                                    | 504        |
+fn code_for(code: tonic::Code) -> i32 {
+    use tonic::Code;
+    match code {
+        Code::Ok => 0,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => 400,
+        Code::Unauthenticated => 401,
+        Code::PermissionDenied => 403,
+        Code::NotFound => 404,
+        Code::AlreadyExists | Code::Aborted => 409,
+        Code::ResourceExhausted => 429,
+        Code::Cancelled => 499,
+        Code::Unknown | Code::Internal | Code::DataLoss => 500,
+        Code::Unimplemented => 501,
+        Code::Unavailable => 503,
+        Code::DeadlineExceeded => 504,
+    }
+}
 
-    let tracer_provider = sdktrace::TracerProvider::builder()
-        .with_resource(resource.clone())
-        .with_batch_exporter(span_exporter, runtime::Tokio)
-        .build();
+/// Rejects a request whose payload is missing or malformed before any work is
+/// done on it
+#[allow(clippy::result_large_err)] // Status is what every handler returns
+fn validate_request(req: &ProcessRequest) -> Result<(), Status> {
+    validate_operation(&req.operation)?;
+    validate_payload(req.payload.as_ref())
+}
 
-    let tracer = tracer_provider.tracer("service-b");
+#[allow(clippy::result_large_err)]
+fn validate_operation(operation: &str) -> Result<(), Status> {
+    match ComputeOperation::parse(operation) {
+        Some(_) => Ok(()),
+        None => Err(Status::invalid_argument(format!(
+            "unsupported operation {:?} (expected sum, mean or max)",
+            operation
+        ))),
+    }
+}
 
-    // Initialize logger provider for OTLP log export
-    let log_exporter = LogExporter::builder()
-        .with_tonic()
-        .with_endpoint(&otlp_endpoint)
-        .build()
-        .expect("Failed to create log exporter");
+/// The checks applied to every payload, whichever RPC it arrives through
+#[allow(clippy::result_large_err)]
+fn validate_payload(payload: Option<&DataPayload>) -> Result<(), Status> {
+    let payload = payload.ok_or_else(|| Status::invalid_argument("payload is required"))?;
+    if payload.id.is_empty() {
+        return Err(Status::invalid_argument("payload.id must not be empty"));
+    }
+    Ok(())
+}
+
+/// Counts a call in `service_b_inflight_requests` while it is alive. The count
+/// is decremented on drop, so early returns, errors and panics are covered.
+struct InflightGuard {
+    metrics: Arc<ServiceBMetrics>,
+    method: &'static str,
+}
+
+impl InflightGuard {
+    fn new(metrics: Arc<ServiceBMetrics>, method: &'static str) -> Self {
+        metrics.record_inflight(method, 1);
+        Self { metrics, method }
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.metrics.record_inflight(self.method, -1);
+    }
+}
+
+/// Records a `ProcessData` call that was dropped before completing, which is
+/// how tonic surfaces a caller cancelling or resetting the stream
+struct CancellationGuard<'a> {
+    metrics: &'a ServiceBMetrics,
+    completed: bool,
+    /// Set by the handler timeout layer, which records the call itself
+    timed_out: Option<HandlerTimedOut>,
+}
 
-    let logger_provider = LoggerProvider::builder()
-        .with_resource(resource.clone())
-        .with_batch_exporter(log_exporter, runtime::Tokio)
-        .build();
+impl Drop for CancellationGuard<'_> {
+    fn drop(&mut self) {
+        let timed_out = self.timed_out.as_ref().is_some_and(HandlerTimedOut::get);
+        if !self.completed && !timed_out {
+            warn!("[Service B] ProcessData cancelled by caller, aborting downstream calls");
+            self.metrics
+                .record_request("ProcessData", RequestStatus::Cancelled);
+        }
+    }
+}
 
-    // Initialize metrics
-    let metric_exporter = MetricExporter::builder()
-        .with_tonic()
-        .with_endpoint(&otlp_endpoint)
+/// Publishes the constant `service_b_build_info` gauge (always 1), whose labels
+/// identify the running build
+fn record_build_info(meter: &Meter, version: &str) {
+    meter
+        .u64_gauge("service_b_build_info")
+        .with_description("Build metadata of the running Service B binary (always 1)")
         .build()
-        .expect("Failed to create metric exporter");
+        .record(
+            1,
+            &[
+                KeyValue::new("version", version.to_string()),
+                KeyValue::new("git_commit", env!("SERVICE_B_GIT_COMMIT")),
+                KeyValue::new("rust_version", env!("SERVICE_B_RUST_VERSION")),
+            ],
+        );
+}
+
+/// Share of the caller's remaining time budget given to a downstream attempt;
+/// the rest is headroom for building and returning the response
+const DOWNSTREAM_DEADLINE_SHARE: f64 = 0.9;
+
+/// The configured per-attempt downstream timeout, shortened to fit within the
+/// caller's deadline when one was propagated
+fn downstream_timeout(configured: Duration, deadline: Option<Instant>) -> Duration {
+    match deadline {
+        Some(deadline) => configured.min(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .mul_f64(DOWNSTREAM_DEADLINE_SHARE),
+        ),
+        None => configured,
+    }
+}
+
+/// Bounds a single downstream attempt, surfacing expiry as `DeadlineExceeded`
+/// so it is retried like any other transient failure. A tonic downstream
+/// enforces the same `grpc-timeout` itself and answers `CANCELLED` when it
+/// fires first, which is the same expiry.
+async fn with_timeout<T>(
+    timeout: Duration,
+    call: impl std::future::Future<Output = Result<T, DownstreamError>>,
+) -> Result<T, DownstreamError> {
+    let start = Instant::now();
+    let expired = || {
+        DownstreamError::Timeout(Status::deadline_exceeded(format!(
+            "timeout after {}ms",
+            timeout.as_millis()
+        )))
+    };
+    match tokio::time::timeout(timeout, call).await {
+        Ok(Err(error)) if error.code() == tonic::Code::Cancelled && start.elapsed() >= timeout => {
+            Err(expired())
+        }
+        Ok(result) => result,
+        Err(_) => Err(expired()),
+    }
+}
+
+fn transport_label(tls: bool) -> &'static str {
+    if tls {
+        "TLS"
+    } else {
+        "plaintext"
+    }
+}
+
+/// Attributes for the processed result: the caller's payload attributes first,
+/// then Service B's own additions, which win on key collision
+fn result_attributes(payload: Option<&DataPayload>) -> HashMap<String, String> {
+    let mut attributes = payload.map(|p| p.attributes.clone()).unwrap_or_default();
+    attributes.insert(
+        String::from("processed_at"),
+        chrono_timestamp_ms().to_string(),
+    );
+    attributes
+}
 
-    let metric_reader = PeriodicReader::builder(metric_exporter, runtime::Tokio)
-        .with_interval(std::time::Duration::from_secs(10))
-        .build();
+/// Completes on Ctrl-C (SIGINT) or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
 
-    let meter_provider = SdkMeterProvider::builder()
-        .with_resource(resource)
-        .with_reader(metric_reader)
-        .build();
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Metadata for a downstream call, carrying the payload's typed attributes so
+/// structured values reach Service D and E without being stringified
+fn downstream_metadata(request_id: &str, payload: Option<&DataPayload>) -> RequestMetadata {
+    RequestMetadata {
+        request_id: request_id.to_string(),
+        trace_id: current_trace_id(),
+        caller_service: String::from("service-b"),
+        timestamp_ms: chrono_timestamp_ms(),
+        attributes: payload
+            .map(|p| p.typed_attributes.clone())
+            .unwrap_or_default(),
+    }
+}
 
-    // Set the global meter provider to prevent it from being dropped
-    opentelemetry::global::set_meter_provider(meter_provider);
+/// Request header asking for a dry run of just this request
+const DRY_RUN_HEADER: &str = "x-dry-run";
 
-    // Create OpenTelemetry tracing layer
-    let otel_trace_layer = tracing_opentelemetry::layer().with_tracer(tracer);
+/// Whether the request sent `x-dry-run: true`
+fn dry_run_requested(metadata: &tonic::metadata::MetadataMap) -> bool {
+    metadata
+        .get(DRY_RUN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
 
-    // Create OpenTelemetry log bridge layer
-    let otel_log_layer = OpenTelemetryTracingBridge::new(&logger_provider);
+/// Source of all randomness (simulated delays, retry jitter, sampling), shared
+/// so a fixed seed gives a reproducible sequence across requests
+pub type SharedRng = Arc<Mutex<StdRng>>;
 
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new("info"))
-        .with(tracing_subscriber::fmt::layer())
-        .with(otel_trace_layer)
-        .with(otel_log_layer)
-        .init();
+/// The shared RNG, seeded from `RNG_SEED` if set and from OS entropy otherwise
+fn shared_rng(seed: Option<u64>) -> SharedRng {
+    Arc::new(Mutex::new(match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }))
+}
 
-    println!("[Service B] OpenTelemetry telemetry initialized, endpoint: {}", otlp_endpoint);
+fn chrono_timestamp_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("[Service B] Initializing OpenTelemetry...");
-    init_telemetry();
+    let config = Config::from_env()?;
 
-    let port = env::var("GRPC_PORT").unwrap_or_else(|_| "50052".into());
-    let service_d_addr = env::var("SERVICE_D_ADDR").unwrap_or_else(|_| "localhost:50054".into());
-    let service_e_addr = env::var("SERVICE_E_ADDR").unwrap_or_else(|_| "localhost:50055".into());
+    println!("[Service B] Initializing OpenTelemetry...");
+    let telemetry = init_telemetry(&config)?;
 
-    let addr = format!("0.0.0.0:{}", port).parse()?;
+    let server_config = &config.server;
+    let downstream = &config.downstream;
+    let processing = &config.processing;
+    let addr = server_config.addr;
+    if server_config.queue.is_some() && server_config.max_concurrent_requests == 0 {
+        println!("[Service B] QUEUE_TIMEOUT_MS has no effect without MAX_CONCURRENT_REQUESTS");
+    }
+    let recent_requests = server_config
+        .recent_requests
+        .map(|capacity| Arc::new(RecentRequests::new(capacity)));
 
     // Create metrics using the global meter provider
     let meter = opentelemetry::global::meter("service-b");
-    let metrics = Arc::new(ServiceBMetrics::new(meter));
+    record_build_info(&meter, &config.telemetry.service_version);
+    let metrics = Arc::new(ServiceBMetrics::new(
+        meter,
+        processing.latency_ema_alpha,
+        processing.constant_labels.clone(),
+    ));
+    if !processing.constant_labels.is_empty() {
+        println!(
+            "[Service B] Constant metric labels: {}",
+            processing
+                .constant_labels
+                .iter()
+                .map(|label| format!("{}={}", label.key, label.value))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    }
 
-    let service = ServiceBImpl::new(service_d_addr.clone(), service_e_addr.clone(), metrics);
+    println!(
+        "[Service B] Downstream connections: {}",
+        transport_label(downstream.tls.is_some())
+    );
+    let rng = shared_rng(processing.rng_seed);
+    let fan_out = Arc::new(FanOutProcessor::new(
+        FanOutConfig {
+            service_d_addr: downstream.service_d_addr.clone(),
+            service_e_addr: downstream.service_e_addr.clone(),
+            service_d_timeout: downstream.service_d_timeout,
+            service_e_timeout: downstream.service_e_timeout,
+            service_e_hedge_delay: downstream.service_e_hedge_delay,
+            service_e_canary: downstream.service_e_canary.clone(),
+            retry_policy: RetryPolicy {
+                max_retries: downstream.max_retries,
+                budget: downstream.retry_budget.map(|budget| {
+                    Arc::new(RetryBudget::new(budget.max_tokens, budget.token_ratio))
+                }),
+                rng: rng.clone(),
+            },
+            breaker_config: downstream.breaker,
+            client_tls: downstream.tls.as_ref().map(|tls| tls.expose().clone()),
+            channel_tuning: downstream.channel_tuning,
+            dns_refresh: downstream.dns_refresh,
+            routing: downstream.routing,
+            compression: server_config.compression,
+            rng: rng.clone(),
+            failure_policies: downstream.failure_policies,
+            processor_id: processing.processor_id.clone(),
+            compute_fallback: processing
+                .compute_fallback
+                .then(|| ComputeFallback::new(processing.compute_fallback_value)),
+            max_message_bytes: server_config.max_message_bytes,
+            identity: telemetry.identity.clone(),
+        },
+        metrics.clone(),
+    )?);
+    let service = ServiceBImpl::new(
+        ServiceBConfig {
+            response_cache: processing.response_cache.clone(),
+            baggage_span_attributes: processing.baggage_span_attributes.clone(),
+            processor_id: processing.processor_id.clone(),
+            rate_limit_rps: processing.rate_limit_rps,
+            rate_limit_labelled_callers: processing.rate_limit_labelled_callers.clone(),
+            payload_redactor: processing.payload_redactor.clone(),
+            idempotency_store: processing.idempotency_store.clone(),
+            slow_request_threshold: processing.slow_request_threshold,
+            dry_run: processing.dry_run,
+            recent_requests: recent_requests.clone(),
+        },
+        fan_out.clone(),
+        metrics.clone(),
+    );
+    // In the background, so an unreachable downstream doesn't hold up startup
+    if downstream.warmup {
+        println!("[Service B] Warming up downstream connections");
+        let fan_out = fan_out.clone();
+        tokio::spawn(async move { fan_out.warmup().await });
+    }
+    if let Some(cache) = &processing.response_cache {
+        println!(
+            "[Service B] Response cache: {} entries, TTL {}s",
+            cache.max_entries,
+            cache.ttl.as_secs()
+        );
+    }
+    if let Some(store) = &processing.idempotency_store {
+        println!(
+            "[Service B] Idempotency keys: {} entries, TTL {}s",
+            store.max_entries,
+            store.ttl.as_secs()
+        );
+    }
+    if processing.compute_fallback {
+        println!(
+            "[Service B] Compute fallback enabled (default value: {:?})",
+            processing.compute_fallback_value
+        );
+    }
+    if processing.payload_redactor.is_some() {
+        println!("[Service B] Payload logging enabled (redacted)");
+    }
+    if let Some(rate_limit_rps) = processing.rate_limit_rps {
+        println!(
+            "[Service B] Rate limit: {} requests/s per caller",
+            rate_limit_rps
+        );
+    }
+    if let Some(budget) = downstream.retry_budget {
+        println!(
+            "[Service B] Retry budget: {} tokens, {} per success",
+            budget.max_tokens, budget.token_ratio
+        );
+    }
+    if downstream.routing == DownstreamRouting::ConsistentHash {
+        println!("[Service B] Downstream routing: consistent hash on payload id");
+    }
+    if processing.dry_run {
+        println!("[Service B] Dry run: ProcessData skips Service D and E");
+    }
+    if let Some(threshold) = processing.slow_request_threshold {
+        println!(
+            "[Service B] Slow request threshold: {}ms",
+            threshold.as_millis()
+        );
+    }
+    if let Some(hedge_delay) = downstream.service_e_hedge_delay {
+        println!(
+            "[Service B] Service E hedge delay: {}ms",
+            hedge_delay.as_millis()
+        );
+    }
+    if let Some(canary) = &downstream.service_e_canary {
+        println!(
+            "[Service B] Mirroring {}% of Service E calls to canary {}",
+            canary.mirror_percent, canary.addr
+        );
+    }
+    if let Some(load_shed) = server_config.load_shed {
+        println!(
+            "[Service B] Load shedding: {}ms latency target, up to {:.0}% shed, {}ms windows",
+            load_shed.target.as_millis(),
+            load_shed.max_shed_rate * 100.0,
+            load_shed.window.as_millis()
+        );
+    }
+    if server_config.max_concurrent_requests > 0 {
+        println!(
+            "[Service B] Max concurrent requests: {}",
+            server_config.max_concurrent_requests
+        );
+        if let Some(queue) = server_config.queue {
+            println!(
+                "[Service B] Request queue: up to {} waiting, {}ms timeout",
+                queue.max_depth,
+                queue.timeout.as_millis()
+            );
+        }
+    }
+    if let Some(handler_timeout) = server_config.handler_timeout {
+        println!(
+            "[Service B] Handler timeout: {}ms",
+            handler_timeout.as_millis()
+        );
+    }
+    println!(
+        "[Service B] Max message size: {} bytes",
+        server_config.max_message_bytes
+    );
 
-    println!("[Service B] Starting gRPC server on port {}", port);
+    println!(
+        "[Service B] Starting gRPC server on {} ({})",
+        addr,
+        transport_label(server_config.tls.is_some())
+    );
     println!("[Service B] Data processor service (Rust) ready");
-    println!("[Service B] Service D address: {}", service_d_addr);
-    println!("[Service B] Service E address: {}", service_e_addr);
+    println!("[Service B] Processor id: {}", processing.processor_id);
+    println!(
+        "[Service B] Proto version: {}",
+        proto_version::PROTO_VERSION
+    );
+    println!(
+        "[Service B] Service D address: {}",
+        downstream.service_d_addr
+    );
+    println!(
+        "[Service B] Service E address: {}",
+        downstream.service_e_addr
+    );
+    println!(
+        "[Service B] Downstream timeouts: D={}ms, E={}ms",
+        downstream.service_d_timeout.as_millis(),
+        downstream.service_e_timeout.as_millis()
+    );
+    let tuning = downstream.channel_tuning;
+    println!(
+        "[Service B] Downstream keepalive: HTTP/2 {}s, TCP {}s (0 = off), connect timeout {}ms",
+        tuning.http2_keepalive_interval.map_or(0, |d| d.as_secs()),
+        tuning.tcp_keepalive.map_or(0, |d| d.as_secs()),
+        tuning.connect_timeout.as_millis()
+    );
+    println!(
+        "[Service B] Downstream failure policies: D={:?}, E={:?}",
+        downstream.failure_policies.service_d, downstream.failure_policies.service_e
+    );
+
+    let ready = Arc::new(AtomicBool::new(false));
+    if let Some(admin_port) = server_config.admin_port {
+        tokio::spawn(admin::serve(
+            admin_port,
+            admin::AdminState {
+                telemetry: telemetry.clone(),
+                ready: ready.clone(),
+                recent_requests,
+            },
+        ));
+    }
+
+    // The overall ("") status is liveness and is always SERVING; the
+    // grpcarch.ServiceB status is readiness and only flips to SERVING once both
+    // downstreams are reachable, so a downstream outage never fails liveness.
+    // The admin /livez and /readyz endpoints report the same two states.
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_not_serving::<ServiceBServer<ServiceBImpl>>()
+        .await;
+    let readiness = {
+        let mut health_reporter = health_reporter.clone();
+        let ready = ready.clone();
+        let addrs = [
+            downstream.service_d_addr.clone(),
+            downstream.service_e_addr.clone(),
+        ];
+        tokio::spawn(async move {
+            readiness::wait_for_downstreams(&[&addrs[0], &addrs[1]]).await;
+            println!("[Service B] Downstreams reachable, reporting ready");
+            ready.store(true, Ordering::Relaxed);
+            health_reporter
+                .set_serving::<ServiceBServer<ServiceBImpl>>()
+                .await;
+        })
+    };
+    if server_config.health_reflects_downstream {
+        println!("[Service B] Health status follows the downstream circuit breakers");
+        tokio::spawn(downstream_health::watch(
+            health_reporter.clone(),
+            ready.clone(),
+            fan_out.circuit_breakers(),
+        ));
+    }
+
+    let reflection_service = if server_config.enable_reflection {
+        println!("[Service B] gRPC server reflection enabled");
+        Some(
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(grpcarch::FILE_DESCRIPTOR_SET)
+                .build_v1()?,
+        )
+    } else {
+        None
+    };
+
+    // Responses are only compressed for callers that advertise gzip in
+    // grpc-accept-encoding; everyone else is answered uncompressed
+    let mut service_b_server =
+        ServiceBServer::new(service).max_decoding_message_size(server_config.max_message_bytes);
+    if let Some(encoding) = server_config.compression {
+        println!("[Service B] gRPC compression: {:?}", encoding);
+        service_b_server = service_b_server
+            .accept_compressed(encoding)
+            .send_compressed(encoding);
+    }
+
+    // Health and reflection stay open so probes and tooling work without a key
+    match &server_config.api_keys {
+        Some(keys) => println!(
+            "[Service B] API key authentication enabled ({} keys)",
+            keys.len()
+        ),
+        None => println!("[Service B] API key authentication disabled"),
+    }
+    let service_b_server = InterceptedService::new(
+        service_b_server,
+        ApiKeyAuth::new(server_config.api_keys.as_deref()),
+    );
+
+    println!(
+        "[Service B] HTTP/2 limits: {} streams/connection, {}B stream window, {}B max frame",
+        server_config.max_concurrent_streams,
+        server_config.initial_stream_window_size,
+        server_config.max_frame_size
+    );
+    // gRPC-Web requests arrive over HTTP/1.1 unless TLS lets the browser
+    // negotiate HTTP/2; native gRPC keeps using HTTP/2 either way
+    let grpc_web = server_config.grpc_web_cors.is_some();
+    if grpc_web {
+        println!("[Service B] gRPC-Web enabled");
+    }
+    let mut builder = Server::builder()
+        .accept_http1(grpc_web)
+        .max_concurrent_streams(server_config.max_concurrent_streams)
+        .initial_stream_window_size(server_config.initial_stream_window_size)
+        .max_frame_size(server_config.max_frame_size);
+    if let Some(tls_config) = &server_config.tls {
+        builder = builder.tls_config(tls_config.clone())?;
+    }
+
+    // GRPC_UDS_PATH replaces the TCP listener with a Unix socket (sidecar
+    // deployments). The socket file is removed when `_socket_file` drops.
+    let unix_listener = server_config
+        .uds_path
+        .as_deref()
+        .map(|path| {
+            println!("[Service B] Listening on Unix socket {}", path.display());
+            uds::bind(path)
+                .map_err(|e| format!("Failed to bind Unix socket {}: {}", path.display(), e))
+        })
+        .transpose()?;
+    let (incoming, _socket_file) = unix_listener.unzip();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    // CORS answers preflights before anything else runs; the gRPC-Web layer
+    // then translates browser requests (and their trailers, which it encodes
+    // into the body) so everything below only ever sees native gRPC
+    let router = builder
+        .layer(tower::util::option_layer(
+            server_config.grpc_web_cors.clone(),
+        ))
+        .layer(tower::util::option_layer(
+            grpc_web.then(tonic_web::GrpcWebLayer::new),
+        ))
+        .layer(middleware::build_layer(MiddlewareConfig {
+            load_shed: server_config.load_shed,
+            max_concurrent_requests: server_config.max_concurrent_requests,
+            queue: server_config.queue,
+            handler_timeout: server_config.handler_timeout,
+            rng,
+            metrics: metrics.clone(),
+        }))
+        .add_service(health_service)
+        .add_service(PayloadSizeLayer::new(metrics).layer(service_b_server))
+        .add_optional_service(reflection_service);
+    let shutdown = async {
+        let _ = shutdown_rx.await;
+    };
+    let mut server = tokio::spawn(async move {
+        match incoming {
+            Some(incoming) => {
+                router
+                    .serve_with_incoming_shutdown(incoming, shutdown)
+                    .await
+            }
+            None => router.serve_with_shutdown(addr, shutdown).await,
+        }
+    });
 
-    Server::builder()
-        .add_service(ServiceBServer::new(service))
-        .serve(addr)
-        .await?;
+    tokio::select! {
+        result = &mut server => {
+            telemetry.shutdown();
+            result??;
+            return Ok(());
+        }
+        _ = shutdown_signal() => {}
+    }
+
+    println!(
+        "[Service B] Shutdown requested, draining in-flight requests (grace: {}s)",
+        server_config.shutdown_grace.as_secs()
+    );
+    readiness.abort();
+    ready.store(false, Ordering::Relaxed);
+    health_reporter
+        .set_not_serving::<ServiceBServer<ServiceBImpl>>()
+        .await;
+    let _ = shutdown_tx.send(());
+
+    match tokio::time::timeout(server_config.shutdown_grace, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            println!("[Service B] Grace period elapsed, aborting remaining requests");
+            server.abort();
+        }
+    }
+
+    println!("[Service B] Flushing telemetry");
+    telemetry.shutdown();
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use tonic::Code;
+
+    fn valid_request() -> ProcessRequest {
+        ProcessRequest {
+            payload: Some(DataPayload {
+                id: String::from("item-1"),
+                ..Default::default()
+            }),
+            operation: String::from("mean"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validate_request_accepts_a_valid_request() {
+        assert!(validate_request(&valid_request()).is_ok());
+        let default_operation = ProcessRequest {
+            operation: String::new(),
+            ..valid_request()
+        };
+        assert!(validate_request(&default_operation).is_ok());
+    }
+
+    #[test]
+    fn validate_request_rejects_a_missing_payload() {
+        let req = ProcessRequest {
+            payload: None,
+            ..valid_request()
+        };
+        let status = validate_request(&req).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "payload is required");
+    }
+
+    #[test]
+    fn validate_request_rejects_an_empty_id() {
+        let req = ProcessRequest {
+            payload: Some(DataPayload::default()),
+            ..valid_request()
+        };
+        let status = validate_request(&req).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "payload.id must not be empty");
+    }
+
+    #[test]
+    fn validate_request_rejects_an_unsupported_operation() {
+        let req = ProcessRequest {
+            operation: String::from("median"),
+            ..valid_request()
+        };
+        let status = validate_request(&req).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(
+            status.message().contains("\"median\""),
+            "{}",
+            status.message()
+        );
+    }
+
+    #[test]
+    fn method_label_buckets_unexpected_methods() {
+        let metrics = ServiceBMetrics::new(
+            opentelemetry::global::meter("service-b-test"),
+            0.2,
+            Vec::new(),
+        );
+        assert_eq!(
+            metrics.method_label("ProcessData"),
+            KeyValue::new("method", "ProcessData")
+        );
+        assert_eq!(
+            metrics.method_label("NotARealMethod"),
+            KeyValue::new("method", known_methods::OTHER_METHOD)
+        );
+    }
+
+    fn downstream_error() -> impl Strategy<Value = DownstreamError> {
+        let message = "[a-z ]{0,20}";
+        prop_oneof![
+            message.prop_map(|m| DownstreamError::Connect(Status::unavailable(m))),
+            message.prop_map(|m| DownstreamError::Rpc {
+                code: Code::Internal,
+                message: m,
+            }),
+            message.prop_map(|m| DownstreamError::Rejected { message: m }),
+            message.prop_map(|m| DownstreamError::Timeout(Status::deadline_exceeded(m))),
+            Just(()).prop_map(|_| DownstreamError::CircuitOpen),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn build_status_agrees_with_its_label(
+            d in proptest::option::of(downstream_error()),
+            e in proptest::option::of(downstream_error()),
+            degraded in any::<bool>(),
+        ) {
+            let failures: Vec<(&str, DownstreamError)> = [("service_d", d), ("service_e", e)]
+                .into_iter()
+                .filter_map(|(name, error)| Some((name, error?)))
+                .collect();
+            let errors: Vec<String> = failures
+                .iter()
+                .map(|(name, e)| format!("{}: {}", name, e))
+                .collect();
+
+            let (status, label) = build_status(&failures, degraded);
+
+            prop_assert_eq!(status.success, failures.is_empty());
+            prop_assert_eq!(status.error_code == 0, failures.is_empty());
+            for error in &errors {
+                prop_assert!(status.message.contains(error.as_str()), "{} missing from {}", error, status.message);
+            }
+            let expected = match failures.len() {
+                0 => (RequestStatus::Ok, "Processing completed successfully"),
+                1 => (RequestStatus::PartialFailure, "Partial failure"),
+                _ => (RequestStatus::Error, "Processing failed"),
+            };
+            prop_assert_eq!(label, expected.0);
+            prop_assert!(status.message.starts_with(expected.1));
+        }
+    }
+}
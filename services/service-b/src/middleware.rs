@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tower::layer::util::{Identity, Stack};
+use tower::util::Either;
+use tower::ServiceBuilder;
+
+use crate::concurrency::{ConcurrencyLimitLayer, QueueConfig};
+use crate::load_shed::{LoadShedConfig, LoadShedLayer};
+use crate::panic::CatchPanicLayer;
+use crate::timeout::HandlerTimeoutLayer;
+use crate::{ServiceBMetrics, SharedRng};
+
+/// Settings for the server-wide middleware stack
+pub struct MiddlewareConfig {
+    /// Reject a share of requests while latency runs over target; `None`
+    /// disables shedding
+    pub load_shed: Option<LoadShedConfig>,
+    /// In-flight request limit; 0 leaves requests unbounded
+    pub max_concurrent_requests: usize,
+    /// Lets requests over the limit wait for a slot instead of failing at once
+    pub queue: Option<QueueConfig>,
+    /// Upper bound on a handler's running time; `None` leaves it unbounded
+    pub handler_timeout: Option<Duration>,
+    pub rng: SharedRng,
+    pub metrics: Arc<ServiceBMetrics>,
+}
+
+/// The stack built by `build_layer` (tower nests the types innermost first)
+pub type MiddlewareLayer = ServiceBuilder<
+    Stack<
+        Either<HandlerTimeoutLayer, Identity>,
+        Stack<
+            Either<ConcurrencyLimitLayer, Identity>,
+            Stack<Either<LoadShedLayer, Identity>, Stack<CatchPanicLayer, Identity>>,
+        >,
+    >,
+>;
+
+/// Composes the cross-cutting layers applied to every service on the server,
+/// for use with `Server::builder().layer(...)`. From the outside in:
+///
+/// 1. Panic catching, so a panic anywhere below (including in the other layers)
+///    still produces an `INTERNAL` response and is counted.
+/// 2. Latency-based load shedding, when enabled. It sits outside the
+///    concurrency limit so the latency it steers by includes time spent
+///    queued, as callers see it, and shed requests cost nothing further.
+/// 3. The concurrency limit, when enabled. Requests over the limit are
+///    rejected here, after waiting in the queue if one is configured, and never
+///    reach a handler or its metrics. Time spent queued is recorded separately
+///    and doesn't count towards the handler's latency.
+/// 4. The handler timeout, when enabled. It sits inside the concurrency limit
+///    so time spent waiting for a slot doesn't count against the handler.
+///
+/// Per-method payload size metrics are applied to `ServiceBServer` itself
+/// rather than here, since the wrapped service must stay a `NamedService`.
+pub fn build_layer(config: MiddlewareConfig) -> MiddlewareLayer {
+    let load_shed = config
+        .load_shed
+        .map(|load_shed| LoadShedLayer::new(load_shed, config.rng.clone(), config.metrics.clone()));
+
+    // 0 (the default) leaves in-flight requests unbounded
+    let concurrency_limit = (config.max_concurrent_requests > 0).then(|| {
+        ConcurrencyLimitLayer::new(
+            config.max_concurrent_requests,
+            config.queue,
+            config.metrics.clone(),
+        )
+    });
+
+    let handler_timeout = config
+        .handler_timeout
+        .map(|timeout| HandlerTimeoutLayer::new(timeout, config.metrics.clone()));
+
+    ServiceBuilder::new()
+        .layer(CatchPanicLayer::new(config.metrics))
+        .option_layer(load_shed)
+        .option_layer(concurrency_limit)
+        .option_layer(handler_timeout)
+}
@@ -0,0 +1,125 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{BoxError, Layer, Service};
+use tracing::error;
+
+use crate::propagation::REQUEST_ID_HEADER;
+use crate::ServiceBMetrics;
+
+/// Turns a panicking handler into an `INTERNAL` status for that one call,
+/// instead of the connection being torn down with no trace in the metrics
+#[derive(Clone)]
+pub struct CatchPanicLayer {
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl CatchPanicLayer {
+    pub fn new(metrics: Arc<ServiceBMetrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanic {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CatchPanic<S> {
+    inner: S,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl<S, B, ResBody> Service<http::Request<B>> for CatchPanic<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let path = req.uri().path().to_string();
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-")
+            .to_string();
+        let metrics = self.metrics.clone();
+
+        // Panics can happen while building the future as well as while polling it
+        let future = match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(req))) {
+            Ok(future) => future,
+            Err(panic) => {
+                return Box::pin(async move { Err(on_panic(&metrics, &path, &request_id, panic)) })
+            }
+        };
+
+        let future = CatchUnwind {
+            inner: Box::pin(future),
+        };
+        Box::pin(async move {
+            match future.await {
+                Ok(result) => result.map_err(Into::into),
+                Err(panic) => Err(on_panic(&metrics, &path, &request_id, panic)),
+            }
+        })
+    }
+}
+
+fn on_panic(
+    metrics: &ServiceBMetrics,
+    path: &str,
+    request_id: &str,
+    panic: Box<dyn Any + Send>,
+) -> BoxError {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>");
+    error!(
+        "[Service B] Handler for {} panicked (request_id: {}): {}",
+        path, request_id, message
+    );
+    metrics.record_panic(path.rsplit('/').next().unwrap_or(path));
+    Box::new(Status::internal("internal error"))
+}
+
+/// Resolves to `Err` with the panic payload if polling `inner` panics
+struct CatchUnwind<F> {
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.inner.as_mut();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http_body::{Body, Frame, SizeHint};
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Bytes};
+use tonic::server::NamedService;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::ServiceBMetrics;
+
+/// Records the encoded request and response body sizes of every call, labelled
+/// by method. Sizes are counted as the bodies stream through, so they include
+/// gRPC framing and reflect compressed bytes when compression is negotiated.
+///
+/// Requests over the server's decoding limit (`MAX_MESSAGE_BYTES`) are
+/// answered with `RESOURCE_EXHAUSTED` rather than tonic's `OUT_OF_RANGE`, so
+/// callers can tell an oversized message from a bad argument.
+#[derive(Clone)]
+pub struct PayloadSizeLayer {
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl PayloadSizeLayer {
+    pub fn new(metrics: Arc<ServiceBMetrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for PayloadSizeLayer {
+    type Service = PayloadSize<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PayloadSize {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PayloadSize<S> {
+    inner: S,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl<S: NamedService> NamedService for PayloadSize<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<http::Request<BoxBody>> for PayloadSize<S>
+where
+    S: Service<http::Request<CountingBody<BoxBody>>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+        let method = method_name(req.uri().path()).to_string();
+        let metrics = self.metrics.clone();
+        let req = req.map(|body| CountingBody {
+            inner: body,
+            bytes: 0,
+            record: Recorder {
+                direction: Direction::Request,
+                method: method.clone(),
+                metrics: metrics.clone(),
+            },
+        });
+
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let mut response = response.await?;
+            reject_oversized_request(&method, response.headers_mut());
+            Ok(response.map(|body| {
+                tonic::body::boxed(CountingBody {
+                    inner: body,
+                    bytes: 0,
+                    record: Recorder {
+                        direction: Direction::Response,
+                        method,
+                        metrics,
+                    },
+                })
+            }))
+        })
+    }
+}
+
+/// Rewrites tonic's decode-limit failure, which comes back as a trailers-only
+/// `OUT_OF_RANGE` response before any handler runs. The handlers never return
+/// `OUT_OF_RANGE` themselves, so the code alone identifies it.
+fn reject_oversized_request(method: &str, headers: &mut http::HeaderMap) {
+    let Some(status) = Status::from_header_map(headers) else {
+        return;
+    };
+    if status.code() != Code::OutOfRange {
+        return;
+    }
+    warn!(
+        "[Service B] Rejected oversized {} request: {}",
+        method,
+        status.message()
+    );
+    // Only fails for a message that can't be header encoded, which this isn't
+    let _ = Status::resource_exhausted(status.message()).add_header(headers);
+}
+
+/// `/grpcarch.ServiceB/ProcessData` -> `ProcessData`
+fn method_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+enum Direction {
+    Request,
+    Response,
+}
+
+struct Recorder {
+    direction: Direction,
+    method: String,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+/// Passes a body through unchanged, counting its data bytes and recording the
+/// total once the body is dropped
+pub struct CountingBody<B> {
+    inner: B,
+    bytes: u64,
+    record: Recorder,
+}
+
+impl<B> Drop for CountingBody<B> {
+    fn drop(&mut self) {
+        match self.record.direction {
+            Direction::Request => self
+                .record
+                .metrics
+                .record_request_bytes(&self.record.method, self.bytes),
+            Direction::Response => self
+                .record
+                .metrics
+                .record_response_bytes(&self.record.method, self.bytes),
+        }
+    }
+}
+
+impl<B> Body for CountingBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                self.bytes += data.len() as u64;
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
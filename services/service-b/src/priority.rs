@@ -0,0 +1,64 @@
+use tonic::codegen::http::HeaderMap;
+use tonic::metadata::MetadataMap;
+
+/// Request header carrying the caller's priority hint
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+/// How much the caller cares about a request being served under overload,
+/// from the `x-priority` header. Interactive traffic sends `high`, batch jobs
+/// `low`; anything else (or nothing) is `normal`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// For the middleware, which sees the raw HTTP request
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self::parse(headers.get(PRIORITY_HEADER).and_then(|v| v.to_str().ok()))
+    }
+
+    pub fn from_metadata(metadata: &MetadataMap) -> Self {
+        Self::parse(metadata.get(PRIORITY_HEADER).and_then(|v| v.to_str().ok()))
+    }
+
+    fn parse(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            Some(v) if v.eq_ignore_ascii_case("high") => Priority::High,
+            Some(v) if v.eq_ignore_ascii_case("low") => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+
+    pub fn as_label(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    /// Multiplies the load-shed rate: low-priority requests are shed at twice
+    /// the rate and high-priority ones at a quarter of it, so under overload
+    /// the low-priority traffic goes first
+    pub fn shed_weight(self) -> f64 {
+        match self {
+            Priority::Low => 2.0,
+            Priority::Normal => 1.0,
+            Priority::High => 0.25,
+        }
+    }
+
+    /// Share of the request queue this priority may fill. Low-priority
+    /// requests stop queueing once it is half full, leaving the rest of the
+    /// room for everyone else.
+    pub fn queue_share(self) -> f64 {
+        match self {
+            Priority::Low => 0.5,
+            Priority::Normal | Priority::High => 1.0,
+        }
+    }
+}
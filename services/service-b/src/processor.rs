@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use tonic::Status;
+
+use crate::grpcarch::{ProcessRequest, ProcessResponse};
+use crate::RequestStatus;
+
+/// The business logic behind every Service B RPC: turns one request (carrying
+/// a single payload) into its response. `ServiceBImpl` handles everything
+/// around it (validation, rate limiting, caching, idempotency, dry runs,
+/// request metrics and logging), so an implementation only does the work
+/// itself. `FanOutProcessor` is the default, calling Service D and E.
+#[tonic::async_trait]
+pub trait Processor: Send + Sync + 'static {
+    async fn process(
+        &self,
+        req: ProcessRequest,
+        ctx: &ProcessContext,
+    ) -> Result<ProcessResponse, ProcessError>;
+}
+
+/// What a `Processor` knows about the call a request arrived on
+#[derive(Clone, Debug)]
+pub struct ProcessContext {
+    /// The RPC, as used in metric labels, e.g. `ProcessData`
+    pub method: &'static str,
+    pub request_id: String,
+    /// When the caller stops waiting, if it set a deadline
+    pub deadline: Option<Instant>,
+    status: OnceLock<RequestStatus>,
+}
+
+impl ProcessContext {
+    pub fn new(method: &'static str, request_id: String, deadline: Option<Instant>) -> Self {
+        Self {
+            method,
+            request_id,
+            deadline,
+            status: OnceLock::new(),
+        }
+    }
+
+    /// Sets the `status` label the request is recorded under. A processor that
+    /// knows which failures count (e.g. which downstreams are required)
+    /// reports it here; otherwise it is worked out from the response.
+    pub fn report_status(&self, status: RequestStatus) {
+        let _ = self.status.set(status);
+    }
+
+    pub fn reported_status(&self) -> Option<RequestStatus> {
+        self.status.get().copied()
+    }
+}
+
+/// A request the processor could not produce a response for. Downstream
+/// failures are not errors: they are reported in the response status, so the
+/// calls that did succeed still reach the caller.
+#[derive(Debug)]
+pub enum ProcessError {
+    /// The request can't be processed as given
+    InvalidRequest(String),
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::InvalidRequest(message) => write!(f, "invalid request: {}", message),
+        }
+    }
+}
+
+impl Error for ProcessError {}
+
+impl From<ProcessError> for Status {
+    fn from(error: ProcessError) -> Self {
+        match error {
+            ProcessError::InvalidRequest(message) => Status::invalid_argument(message),
+        }
+    }
+}
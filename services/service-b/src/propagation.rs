@@ -0,0 +1,149 @@
+use std::time::{Duration, Instant};
+
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TraceContextExt;
+use tonic::metadata::{KeyRef, MetadataKey, MetadataMap, MetadataValue};
+use tonic::Request;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Adapts outgoing gRPC metadata to the OpenTelemetry `Injector` interface
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value.as_str()),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// Adapts incoming gRPC metadata to the OpenTelemetry `Extractor` interface
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|k| match k {
+                KeyRef::Ascii(k) => Some(k.as_str()),
+                KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Continues the caller's trace by parenting the current span on the context
+/// carried in the incoming request headers (if any). Incoming baggage rides
+/// along in that context, so it is re-emitted on every downstream call; the
+/// entries named in `baggage_span_attributes` are also set on the current span.
+pub fn extract_trace_context<T>(req: &Request<T>, baggage_span_attributes: &[String]) {
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetadataExtractor(req.metadata()))
+    });
+    if !parent.span().span_context().is_valid() && parent.baggage().is_empty() {
+        return;
+    }
+
+    let span = tracing::Span::current();
+    for key in baggage_span_attributes {
+        if let Some(value) = parent.baggage().get(key.as_str()) {
+            span.set_attribute(key.clone(), value.clone());
+        }
+    }
+    span.set_parent(parent);
+}
+
+/// Writes the current span context into the outgoing request headers
+pub fn inject_trace_context<T>(req: &mut Request<T>) {
+    let cx = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut MetadataInjector(req.metadata_mut()))
+    });
+}
+
+/// Metadata header carrying the logical request id across services
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The caller-supplied `x-request-id`, or a freshly generated UUIDv4 when absent
+pub fn incoming_request_id<T>(req: &Request<T>) -> String {
+    req.metadata()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Stamps the request id onto outgoing request (or response) metadata
+pub fn inject_request_id(metadata: &mut MetadataMap, request_id: &str) {
+    if let Ok(value) = MetadataValue::try_from(request_id) {
+        metadata.insert(REQUEST_ID_HEADER, value);
+    }
+}
+
+/// Hex-encoded trace id of the current span, or empty when there is no active trace
+pub fn current_trace_id() -> String {
+    let cx = tracing::Span::current().context();
+    let span = cx.span();
+    let span_context = span.span_context();
+    if span_context.is_valid() {
+        span_context.trace_id().to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Sets the OTel status of the current span through the `otel.status_code` and
+/// `otel.status_description` fields, which the span must declare. Failed spans
+/// then show up as errors in the trace view instead of looking successful.
+pub fn record_span_status(error: Option<&str>) {
+    let span = tracing::Span::current();
+    match error {
+        None => {
+            span.record("otel.status_code", "OK");
+        }
+        Some(description) => {
+            span.record("otel.status_code", "ERROR")
+                .record("otel.status_description", description);
+        }
+    }
+}
+
+/// Metadata header carrying the caller's remaining deadline
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// The caller's deadline from the `grpc-timeout` header, measured from `received`
+pub fn incoming_deadline<T>(req: &Request<T>, received: Instant) -> Option<Instant> {
+    req.metadata()
+        .get(GRPC_TIMEOUT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_grpc_timeout)
+        .map(|timeout| received + timeout)
+}
+
+/// Parses a `grpc-timeout` value: up to 8 digits followed by a unit
+/// (`H`, `M`, `S`, `m`, `u`, `n`)
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 || !value.is_ascii() {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = digits.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+use tonic::metadata::MetadataMap;
+use tracing::warn;
+
+/// Hash of the `grpcarch` `.proto` sources this binary was built from (see
+/// build.rs)
+pub const PROTO_VERSION: &str = env!("GRPCARCH_PROTO_VERSION");
+
+/// Metadata header a peer advertises its own proto version in
+pub const PROTO_VERSION_HEADER: &str = "x-proto-version";
+
+/// Peer and version pairs already warned about, so a skewed peer is reported
+/// once rather than on every call
+static REPORTED_SKEW: OnceLock<Mutex<HashSet<(String, String)>>> = OnceLock::new();
+
+/// Warns if `peer` advertised a proto version different from this build's.
+/// Peers that don't send the header are assumed to match.
+pub fn check_peer(peer: &str, metadata: &MetadataMap) {
+    let Some(theirs) = metadata
+        .get(PROTO_VERSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return;
+    };
+    if theirs == PROTO_VERSION {
+        return;
+    }
+    let first_report = REPORTED_SKEW
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .insert((peer.to_string(), theirs.to_string()));
+    if first_report {
+        warn!(
+            "[Service B] PROTO SCHEMA SKEW: {} was built from proto version {}, this build from {}; \
+             calls may fail or drop fields until both run the same schema",
+            peer, theirs, PROTO_VERSION
+        );
+    }
+}
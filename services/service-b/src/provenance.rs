@@ -0,0 +1,45 @@
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::proto_version::{PROTO_VERSION, PROTO_VERSION_HEADER};
+use crate::telemetry::ServiceIdentity;
+
+const SERVICE_NAME_HEADER: &str = "x-service-name";
+const SERVICE_VERSION_HEADER: &str = "x-service-version";
+
+/// Stamps `x-service-name`, `x-service-version` and `x-proto-version` on every
+/// outgoing call, so a downstream can tell which build of Service B sent it
+/// when chasing version skew. Values that aren't valid header text are left
+/// off.
+#[derive(Clone)]
+pub struct ServiceIdentityInterceptor {
+    name: Option<MetadataValue<Ascii>>,
+    version: Option<MetadataValue<Ascii>>,
+}
+
+impl ServiceIdentityInterceptor {
+    pub fn new(identity: &ServiceIdentity) -> Self {
+        Self {
+            name: identity.name.parse().ok(),
+            version: identity.version.parse().ok(),
+        }
+    }
+}
+
+impl Interceptor for ServiceIdentityInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let metadata = request.metadata_mut();
+        if let Some(name) = &self.name {
+            metadata.insert(SERVICE_NAME_HEADER, name.clone());
+        }
+        if let Some(version) = &self.version {
+            metadata.insert(SERVICE_VERSION_HEADER, version.clone());
+        }
+        metadata.insert(
+            PROTO_VERSION_HEADER,
+            MetadataValue::from_static(PROTO_VERSION),
+        );
+        Ok(request)
+    }
+}
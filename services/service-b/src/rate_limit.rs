@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use tonic::Request;
+
+/// Metadata header naming the calling service, used as the rate limit key
+pub const CALLER_SERVICE_HEADER: &str = "caller-service";
+
+/// Label for a rate-limited caller that isn't one of the labelled callers
+pub const OTHER_CALLER: &str = "other";
+
+/// Callers tracked at once. Past this the least recently seen is forgotten,
+/// and starts again with a full bucket if it comes back.
+const MAX_TRACKED_CALLERS: NonZeroUsize = match NonZeroUsize::new(10_000) {
+    Some(capacity) => capacity,
+    None => unreachable!(),
+};
+
+/// Token bucket per caller: each caller may make `rps` requests per second on
+/// average, with bursts of up to one second's worth
+pub struct RateLimiter {
+    rps: f64,
+    burst: f64,
+    buckets: Mutex<LruCache<String, Bucket>>,
+    /// Callers named in the rejection metric; the rest share `other`
+    labelled_callers: HashSet<String>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rps: f64, labelled_callers: Vec<String>) -> Self {
+        Self {
+            rps,
+            burst: rps.max(1.0),
+            buckets: Mutex::new(LruCache::new(MAX_TRACKED_CALLERS)),
+            labelled_callers: labelled_callers.into_iter().collect(),
+        }
+    }
+
+    /// Takes a token for `caller`, or returns how long until one is available
+    pub fn try_acquire(&self, caller: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_or_insert_mut(caller.to_string(), || Bucket {
+            tokens: self.burst,
+            refilled_at: now,
+        });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rps))
+        }
+    }
+
+    /// The bucket's token count as of `now`
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.rps).min(self.burst)
+    }
+
+    /// The `caller` metric label: `caller` if it is a labelled caller, `other`
+    /// otherwise, so callers (or peer IPs) can't add label values at will
+    pub fn caller_label<'a>(&self, caller: &'a str) -> &'a str {
+        if self.labelled_callers.contains(caller) {
+            caller
+        } else {
+            OTHER_CALLER
+        }
+    }
+}
+
+/// The `caller-service` header, if the caller sent a non-empty one
+pub fn caller_service<T>(req: &Request<T>) -> Option<String> {
+    req.metadata()
+        .get(CALLER_SERVICE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+}
+
+/// The `caller-service` header, falling back to the peer IP address
+pub fn caller_key<T>(req: &Request<T>) -> String {
+    caller_service(req)
+        .or_else(|| req.remote_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_caller_past_its_burst() {
+        let limiter = RateLimiter::new(2.0, Vec::new());
+
+        assert!(limiter.try_acquire("service-a").is_ok());
+        assert!(limiter.try_acquire("service-a").is_ok());
+        let retry_after = limiter.try_acquire("service-a").unwrap_err();
+        assert!(
+            retry_after <= Duration::from_millis(500),
+            "{:?}",
+            retry_after
+        );
+        // Every caller has a bucket of its own
+        assert!(limiter.try_acquire("service-c").is_ok());
+    }
+
+    #[test]
+    fn tracks_a_bounded_number_of_callers() {
+        let limiter = RateLimiter::new(1.0, Vec::new());
+        limiter.try_acquire("first").unwrap();
+        assert!(limiter.try_acquire("first").is_err());
+
+        for caller in 0..MAX_TRACKED_CALLERS.get() {
+            limiter.try_acquire(&caller.to_string()).unwrap();
+        }
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_CALLERS.get());
+        assert!(
+            !buckets.contains("first"),
+            "least recently seen is forgotten"
+        );
+    }
+
+    #[test]
+    fn labels_only_listed_callers() {
+        let limiter = RateLimiter::new(1.0, vec![String::from("service-a")]);
+
+        assert_eq!(limiter.caller_label("service-a"), "service-a");
+        assert_eq!(limiter.caller_label("service-z"), OTHER_CALLER);
+        assert_eq!(limiter.caller_label("10.0.0.7"), OTHER_CALLER);
+    }
+}
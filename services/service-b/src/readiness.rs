@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use tokio::net::{TcpStream, UnixStream};
+use tracing::warn;
+
+/// Each connection attempt is abandoned after this long
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+const PROBE_ATTEMPTS: u32 = 3;
+const PROBE_BACKOFF: Duration = Duration::from_millis(200);
+/// Pause between failed readiness rounds while waiting for downstreams
+const READINESS_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One-shot connectivity check against a (possibly comma-separated) downstream
+/// address: true as soon as any target accepts a connection, within
+/// `PROBE_ATTEMPTS` bounded attempts.
+pub async fn probe_downstream(addr: &str) -> bool {
+    let targets: Vec<&str> = addr
+        .split(',')
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .collect();
+
+    for attempt in 1..=PROBE_ATTEMPTS {
+        for target in &targets {
+            let connect = async {
+                match crate::uds::unix_path(target) {
+                    Some(path) => UnixStream::connect(path).await.map(drop),
+                    None => TcpStream::connect(target).await.map(drop),
+                }
+            };
+            match tokio::time::timeout(PROBE_TIMEOUT, connect).await {
+                Ok(Ok(())) => return true,
+                Ok(Err(e)) => warn!("[Service B] Readiness probe to {} failed: {}", target, e),
+                Err(_) => warn!(
+                    "[Service B] Readiness probe to {} timed out after {}ms",
+                    target,
+                    PROBE_TIMEOUT.as_millis()
+                ),
+            }
+        }
+        if attempt < PROBE_ATTEMPTS {
+            tokio::time::sleep(PROBE_BACKOFF).await;
+        }
+    }
+    false
+}
+
+/// Resolves once every downstream passes `probe_downstream`, re-probing the
+/// whole set until they do
+pub async fn wait_for_downstreams(addrs: &[&str]) {
+    loop {
+        let mut ready = true;
+        for addr in addrs {
+            ready &= probe_downstream(addr).await;
+        }
+        if ready {
+            return;
+        }
+        tokio::time::sleep(READINESS_RETRY_INTERVAL).await;
+    }
+}
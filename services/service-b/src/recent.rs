@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use crate::RequestStatus;
+
+/// The last few requests processed, so a request id from a user report can be
+/// matched to its trace id without searching the tracing backend. Served by
+/// the admin `GET /recent` endpoint.
+pub struct RecentRequests {
+    entries: Mutex<VecDeque<RecentRequest>>,
+    capacity: NonZeroUsize,
+}
+
+struct RecentRequest {
+    request_id: String,
+    trace_id: String,
+    method: String,
+    duration_ms: i64,
+    status: RequestStatus,
+}
+
+impl RecentRequests {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity.get())),
+            capacity,
+        }
+    }
+
+    /// Records a finished request, dropping the oldest once full
+    pub fn record(
+        &self,
+        request_id: &str,
+        trace_id: String,
+        method: &str,
+        duration_ms: i64,
+        status: RequestStatus,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity.get() {
+            entries.pop_front();
+        }
+        entries.push_back(RecentRequest {
+            request_id: request_id.to_string(),
+            trace_id,
+            method: method.to_string(),
+            duration_ms,
+            status,
+        });
+    }
+
+    /// Newest first, optionally only those with the given request id
+    pub fn to_json(&self, request_id: Option<&str>) -> Value {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .filter(|entry| request_id.is_none_or(|id| entry.request_id == id))
+            .map(|entry| {
+                json!({
+                    "request_id": entry.request_id,
+                    "trace_id": entry.trace_id,
+                    "method": entry.method,
+                    "duration_ms": entry.duration_ms,
+                    "status": entry.status.as_label(),
+                })
+            })
+            .collect()
+    }
+}
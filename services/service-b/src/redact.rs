@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+
+use crate::grpcarch::typed_value::Value;
+use crate::grpcarch::{DataPayload, ProcessRequest, ProcessResponse, TypedValue};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Produces copies of requests and responses that are safe to log: attributes
+/// named in `redact_keys` (case-insensitive) are masked and payload content is
+/// truncated; typed attributes are masked the same way. Only the copies are
+/// ever logged, never the originals.
+#[derive(Clone, Debug)]
+pub struct PayloadRedactor {
+    redact_keys: HashSet<String>,
+    max_content_len: usize,
+}
+
+impl PayloadRedactor {
+    pub fn new(redact_keys: impl IntoIterator<Item = String>, max_content_len: usize) -> Self {
+        Self {
+            redact_keys: redact_keys
+                .into_iter()
+                .map(|key| key.to_ascii_lowercase())
+                .collect(),
+            max_content_len,
+        }
+    }
+
+    pub fn request(&self, req: &ProcessRequest) -> ProcessRequest {
+        ProcessRequest {
+            payload: req.payload.as_ref().map(|p| self.payload(p)),
+            payloads: req.payloads.iter().map(|p| self.payload(p)).collect(),
+            ..req.clone()
+        }
+    }
+
+    pub fn response(&self, response: &ProcessResponse) -> ProcessResponse {
+        ProcessResponse {
+            result: response.result.as_ref().map(|p| self.payload(p)),
+            ..response.clone()
+        }
+    }
+
+    fn payload(&self, payload: &DataPayload) -> DataPayload {
+        DataPayload {
+            id: payload.id.clone(),
+            content: self.truncate(&payload.content),
+            attributes: payload
+                .attributes
+                .iter()
+                .map(|(key, value)| {
+                    let value = if self.is_redacted(key) {
+                        REDACTED.to_string()
+                    } else {
+                        value.clone()
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+            typed_attributes: payload
+                .typed_attributes
+                .iter()
+                .map(|(key, value)| {
+                    let value = if self.is_redacted(key) {
+                        TypedValue {
+                            value: Some(Value::StringValue(REDACTED.to_string())),
+                        }
+                    } else {
+                        value.clone()
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        }
+    }
+
+    fn is_redacted(&self, key: &str) -> bool {
+        self.redact_keys.contains(&key.to_ascii_lowercase())
+    }
+
+    fn truncate(&self, content: &str) -> String {
+        match content.char_indices().nth(self.max_content_len) {
+            Some((end, _)) => format!("{}... ({} bytes)", &content[..end], content.len()),
+            None => content.to_string(),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::Rng;
+use tonic::Code;
+use tracing::warn;
+
+use crate::downstream_error::DownstreamError;
+use crate::{ServiceBMetrics, SharedRng};
+
+const BASE_BACKOFF: Duration = Duration::from_millis(25);
+const MAX_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Retry settings shared by all downstream calls
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Shared by every downstream, so an outage of either one throttles both
+    pub budget: Option<Arc<RetryBudget>>,
+    /// Draws the backoff jitter
+    pub rng: SharedRng,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with full jitter: a random delay in `[0, base * 2^attempt]`,
+    /// capped at `MAX_BACKOFF`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = BASE_BACKOFF
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_BACKOFF);
+        let jitter_ms = self
+            .rng
+            .lock()
+            .unwrap()
+            .gen_range(0..=ceiling.as_millis() as u64);
+        Duration::from_millis(jitter_ms)
+    }
+}
+
+/// Retry throttling as in gRFC A6: a token bucket that starts full at
+/// `max_tokens`, loses a token for every retryable failure and gains
+/// `token_ratio` for every success. Retries are only allowed while more than
+/// half the tokens remain, so once failures outnumber successes by roughly
+/// `1 / token_ratio` to one, calls fail fast instead of piling on retries.
+#[derive(Debug)]
+pub struct RetryBudget {
+    max_tokens: f64,
+    token_ratio: f64,
+    tokens: Mutex<f64>,
+}
+
+impl RetryBudget {
+    pub fn new(max_tokens: f64, token_ratio: f64) -> Self {
+        Self {
+            max_tokens,
+            token_ratio,
+            tokens: Mutex::new(max_tokens),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.token_ratio).min(self.max_tokens);
+    }
+
+    /// Records a retryable failure and reports whether it may be retried
+    fn record_failure(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens - 1.0).max(0.0);
+        *tokens > self.max_tokens / 2.0
+    }
+}
+
+/// Only transient failures are worth retrying. A call the breaker rejected is
+/// not: it will keep being rejected until the cooldown ends.
+pub fn is_retryable(error: &DownstreamError) -> bool {
+    match error {
+        DownstreamError::Connect(_) | DownstreamError::Timeout(_) => true,
+        DownstreamError::Rpc { code, .. } => *code == Code::ResourceExhausted,
+        DownstreamError::Rejected { .. } | DownstreamError::CircuitOpen => false,
+    }
+}
+
+/// Runs `op` and retries it on retryable errors, up to `policy.max_retries`
+/// additional attempts and while the retry budget allows. Non-retryable errors
+/// are returned immediately.
+pub async fn retry_async<T, F, Fut>(
+    policy: &RetryPolicy,
+    downstream: &str,
+    metrics: &ServiceBMetrics,
+    mut op: F,
+) -> Result<T, DownstreamError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DownstreamError>>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = op().await;
+        // Every attempt counts towards the budget, retries included
+        let within_budget = match (&policy.budget, &result) {
+            (Some(budget), Ok(_)) => {
+                budget.record_success();
+                true
+            }
+            (Some(budget), Err(error)) if is_retryable(error) => budget.record_failure(),
+            _ => true,
+        };
+        match result {
+            Ok(value) => return Ok(value),
+            Err(error)
+                if attempt < policy.max_retries && is_retryable(&error) && !within_budget =>
+            {
+                warn!(
+                    "[Service B] {} call failed ({}), retry budget exhausted, not retrying",
+                    downstream,
+                    error.code()
+                );
+                metrics.record_retry_throttled(downstream);
+                return Err(error);
+            }
+            Err(error) if attempt < policy.max_retries && is_retryable(&error) => {
+                attempt += 1;
+                let delay = policy.backoff(attempt);
+                warn!(
+                    "[Service B] {} call failed ({}), retry attempt {}/{} in {}ms",
+                    downstream,
+                    error.code(),
+                    attempt,
+                    policy.max_retries,
+                    delay.as_millis()
+                );
+                metrics.record_retry(downstream);
+                tokio::time::sleep(delay).await;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_rng;
+
+    fn policy(seed: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            budget: None,
+            rng: shared_rng(Some(seed)),
+        }
+    }
+
+    #[test]
+    fn backoff_is_reproducible_from_the_seed() {
+        let backoffs = |policy: RetryPolicy| -> Vec<Duration> {
+            (0..8).map(|attempt| policy.backoff(attempt)).collect()
+        };
+        assert_eq!(backoffs(policy(7)), backoffs(policy(7)));
+    }
+
+    #[test]
+    fn backoff_stays_under_its_ceiling() {
+        let policy = policy(7);
+        for attempt in 0..32 {
+            let ceiling = BASE_BACKOFF
+                .saturating_mul(1 << attempt.min(16))
+                .min(MAX_BACKOFF);
+            assert!(policy.backoff(attempt) <= ceiling);
+        }
+    }
+}
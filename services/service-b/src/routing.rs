@@ -0,0 +1,283 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::sync::mpsc;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tower::discover::Change;
+use tower::util::Either;
+use tower::{BoxError, Service, ServiceExt};
+use tracing::warn;
+
+use crate::discovery::{spawn_dns_discovery, SystemResolver};
+use crate::grpcarch::DataPayload;
+use crate::propagation::current_trace_id;
+use crate::{build_endpoint, ChannelTuning, SharedRng};
+
+/// A replica that failed to connect is skipped for this long
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// How calls are spread across a downstream's replicas
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DownstreamRouting {
+    /// tonic's load balancer (power of two choices)
+    #[default]
+    Balanced,
+    /// Calls with the same routing key go to the same replica
+    ConsistentHash,
+}
+
+/// Routing from `DOWNSTREAM_ROUTING`: `balanced` (the default) or
+/// `consistent-hash`
+pub fn load_routing() -> Result<DownstreamRouting, Box<dyn Error>> {
+    match env::var("DOWNSTREAM_ROUTING").as_deref().map(str::trim) {
+        Err(_) | Ok("balanced") => Ok(DownstreamRouting::Balanced),
+        Ok("consistent-hash") => Ok(DownstreamRouting::ConsistentHash),
+        Ok(other) => Err(format!("Invalid value for DOWNSTREAM_ROUTING: {:?}", other).into()),
+    }
+}
+
+/// The client transport for a downstream, whichever routing it uses
+pub type DownstreamChannel = Either<Channel, HashRoutedChannel>;
+
+/// Request extension choosing the replica under consistent-hash routing
+#[derive(Clone, Copy, Debug)]
+pub struct RoutingKey(u64);
+
+/// Keyed by the payload id, or the trace id for requests without one, so
+/// repeated work on the same data lands on the replica that has it cached.
+/// `None` when there is neither.
+pub fn routing_key(payload: Option<&DataPayload>) -> Option<RoutingKey> {
+    let key = payload
+        .map(|p| p.id.clone())
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(current_trace_id);
+    if key.is_empty() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    Some(RoutingKey(hasher.finish()))
+}
+
+/// Channel that sends each call to the replica picked by rendezvous hashing of
+/// its `RoutingKey` over the healthy replicas. A replica joining or leaving
+/// only moves the keys it wins or owned; calls without a key go to a replica
+/// picked by a key drawn from the shared RNG. When every replica is unhealthy,
+/// all of them are candidates again.
+#[derive(Clone)]
+pub struct HashRoutedChannel {
+    replicas: Arc<RwLock<BTreeMap<String, Arc<Replica>>>>,
+    rng: SharedRng,
+}
+
+struct Replica {
+    name: String,
+    channel: Channel,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn is_healthy(&self) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn mark_unhealthy(&self) {
+        *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+    }
+
+    fn weight(&self, key: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.name.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl HashRoutedChannel {
+    fn new(rng: SharedRng) -> Self {
+        Self {
+            replicas: Arc::default(),
+            rng,
+        }
+    }
+
+    fn insert(&self, name: String, channel: Channel) {
+        let replica = Arc::new(Replica {
+            name: name.clone(),
+            channel,
+            unhealthy_until: Mutex::new(None),
+        });
+        self.replicas.write().unwrap().insert(name, replica);
+    }
+
+    fn remove(&self, name: &str) {
+        self.replicas.write().unwrap().remove(name);
+    }
+
+    fn pick(&self, key: u64) -> Option<Arc<Replica>> {
+        let replicas = self.replicas.read().unwrap();
+        let highest = |healthy_only: bool| {
+            replicas
+                .values()
+                .filter(|replica| !healthy_only || replica.is_healthy())
+                .max_by_key(|replica| replica.weight(key))
+                .cloned()
+        };
+        highest(true).or_else(|| highest(false))
+    }
+
+    /// Applies endpoint changes from DNS discovery until the sender goes away
+    async fn follow(self, mut changes: mpsc::Receiver<Change<SocketAddr, Endpoint>>) {
+        while let Some(change) = changes.recv().await {
+            match change {
+                Change::Insert(addr, endpoint) => {
+                    self.insert(addr.to_string(), endpoint.connect_lazy())
+                }
+                Change::Remove(addr) => self.remove(&addr.to_string()),
+            }
+        }
+    }
+}
+
+impl Service<http::Request<BoxBody>> for HashRoutedChannel {
+    type Response = http::Response<BoxBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    /// Always ready: the replica is only known once the request is, so its
+    /// channel is driven to readiness in `call`
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+        let key = match req.extensions().get::<RoutingKey>() {
+            Some(key) => key.0,
+            None => self.rng.lock().unwrap().gen(),
+        };
+        let replica = self.pick(key);
+
+        Box::pin(async move {
+            let replica = replica.ok_or("no downstream replicas available")?;
+            match replica.channel.clone().oneshot(req).await {
+                Ok(response) => Ok(response),
+                // Only transport failures get here; RPC errors are responses
+                Err(e) => {
+                    warn!(
+                        "[Service B] Replica {} failed, skipping it for {}s: {}",
+                        replica.name,
+                        UNHEALTHY_COOLDOWN.as_secs(),
+                        e
+                    );
+                    replica.mark_unhealthy();
+                    Err(e.into())
+                }
+            }
+        })
+    }
+}
+
+/// Consistent-hash channel over the comma-separated `host:port` addresses, or
+/// over every IP they resolve to when `refresh` enables DNS discovery
+pub fn hash_routed_channel(
+    addrs: &str,
+    tls: Option<&ClientTlsConfig>,
+    tuning: ChannelTuning,
+    refresh: Option<Duration>,
+    rng: SharedRng,
+) -> Result<HashRoutedChannel, Box<dyn Error>> {
+    let channel = HashRoutedChannel::new(rng);
+    match refresh {
+        Some(refresh) => {
+            let (changes, received) = mpsc::channel(64);
+            spawn_dns_discovery(addrs, tls, tuning, refresh, SystemResolver, changes)?;
+            tokio::spawn(channel.clone().follow(received));
+        }
+        None => {
+            for addr in addrs.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+                channel.insert(
+                    addr.to_string(),
+                    build_endpoint(addr, tls, tuning)?.connect_lazy(),
+                );
+            }
+            if channel.replicas.read().unwrap().is_empty() {
+                return Err(format!("No downstream address in {:?}", addrs).into());
+            }
+        }
+    }
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_rng;
+
+    fn channel(replicas: &[&str]) -> HashRoutedChannel {
+        let channel = HashRoutedChannel::new(shared_rng(Some(0)));
+        for name in replicas {
+            let endpoint = Endpoint::from_shared(format!("http://{}", name)).unwrap();
+            channel.insert(name.to_string(), endpoint.connect_lazy());
+        }
+        channel
+    }
+
+    fn picked(channel: &HashRoutedChannel, key: u64) -> String {
+        channel.pick(key).unwrap().name.clone()
+    }
+
+    #[tokio::test]
+    async fn same_key_picks_the_same_replica() {
+        let channel = channel(&["10.0.0.1:50054", "10.0.0.2:50054", "10.0.0.3:50054"]);
+        for key in 0..64 {
+            let first = picked(&channel, key);
+            assert!((0..8).all(|_| picked(&channel, key) == first));
+        }
+    }
+
+    #[tokio::test]
+    async fn unhealthy_replica_is_skipped_until_its_cooldown_ends() {
+        let channel = channel(&["10.0.0.1:50054", "10.0.0.2:50054"]);
+        let key = 42;
+        let owner = channel.pick(key).unwrap();
+
+        owner.mark_unhealthy();
+        assert_ne!(picked(&channel, key), owner.name);
+
+        // Cooldown over
+        *owner.unhealthy_until.lock().unwrap() = Some(Instant::now());
+        assert_eq!(picked(&channel, key), owner.name);
+    }
+
+    // One test, since every case sets the same process-wide variable
+    #[test]
+    fn routing_is_loaded_from_the_environment() {
+        env::remove_var("DOWNSTREAM_ROUTING");
+        assert_eq!(load_routing().unwrap(), DownstreamRouting::Balanced);
+
+        env::set_var("DOWNSTREAM_ROUTING", " consistent-hash ");
+        assert_eq!(load_routing().unwrap(), DownstreamRouting::ConsistentHash);
+
+        env::set_var("DOWNSTREAM_ROUTING", "round-robin");
+        assert_eq!(
+            load_routing().unwrap_err().to_string(),
+            "Invalid value for DOWNSTREAM_ROUTING: \"round-robin\""
+        );
+        env::remove_var("DOWNSTREAM_ROUTING");
+    }
+}
@@ -0,0 +1,882 @@
+use std::env;
+use std::time::Duration;
+
+use axum::response::IntoResponse;
+use opentelemetry::propagation::TextMapCompositePropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{Key, KeyValue};
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_otlp::{LogExporter, MetricExporter, Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_resource_detectors::{HostResourceDetector, ProcessResourceDetector};
+use opentelemetry_sdk::logs::{self as sdklogs, BatchLogProcessor, LoggerProvider};
+use opentelemetry_sdk::metrics::{
+    new_view, Aggregation, Instrument, PeriodicReader, SdkMeterProvider, Stream,
+};
+use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+use opentelemetry_sdk::resource::{EnvResourceDetector, ResourceDetector};
+use opentelemetry_sdk::{runtime, trace as sdktrace, Resource};
+use prometheus::Encoder;
+
+use crate::config::{Config, ConfigError};
+use crate::export_retry::{ExportRetry, RetryingLogExporter, RetryingSpanExporter};
+use crate::log_format::FlattenedJson;
+use tracing_subscriber::fmt::format::JsonFields;
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+/// Handles to the OpenTelemetry providers, kept so buffered telemetry can be
+/// flushed on shutdown. A provider is `None` when its signal is disabled.
+#[derive(Clone)]
+pub struct TelemetryProviders {
+    tracer_provider: Option<sdktrace::TracerProvider>,
+    logger_provider: Option<LoggerProvider>,
+    meter_provider: Option<SdkMeterProvider>,
+    /// Runtime handle to the log filter, used by the admin endpoint
+    pub log_filter: LogFilterHandle,
+    /// Name and version this process reports itself as
+    pub identity: ServiceIdentity,
+}
+
+/// `service.name` and `service.version` as resolved into the resource, so
+/// outgoing calls advertise the same identity the telemetry does
+#[derive(Clone, Debug)]
+pub struct ServiceIdentity {
+    pub name: String,
+    pub version: String,
+}
+
+/// Swaps the active `EnvFilter` without restarting the process
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+impl TelemetryProviders {
+    /// Exports everything buffered by the batch processors and the periodic
+    /// metric reader right away. Returns a description of each failure.
+    pub fn force_flush(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(tracer_provider) = &self.tracer_provider {
+            for result in tracer_provider.force_flush() {
+                if let Err(e) = result {
+                    errors.push(format!("traces: {}", e));
+                }
+            }
+        }
+        if let Some(meter_provider) = &self.meter_provider {
+            if let Err(e) = meter_provider.force_flush() {
+                errors.push(format!("metrics: {}", e));
+            }
+        }
+        if let Some(logger_provider) = &self.logger_provider {
+            for result in logger_provider.force_flush() {
+                if let Err(e) = result {
+                    errors.push(format!("logs: {}", e));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Flushes and shuts down all exporters. Errors are reported but not fatal,
+    /// since the process is exiting anyway.
+    pub fn shutdown(&self) {
+        if let Some(tracer_provider) = &self.tracer_provider {
+            for result in tracer_provider.force_flush() {
+                if let Err(e) = result {
+                    eprintln!("[Service B] Failed to flush traces: {}", e);
+                }
+            }
+            if let Err(e) = tracer_provider.shutdown() {
+                eprintln!("[Service B] Failed to shut down tracer provider: {}", e);
+            }
+        }
+        if let Some(meter_provider) = &self.meter_provider {
+            if let Err(e) = meter_provider.shutdown() {
+                eprintln!("[Service B] Failed to shut down meter provider: {}", e);
+            }
+        }
+        if let Some(logger_provider) = &self.logger_provider {
+            if let Err(e) = logger_provider.shutdown() {
+                eprintln!("[Service B] Failed to shut down logger provider: {}", e);
+            }
+        }
+    }
+}
+
+/// Telemetry settings, read as part of `Config::from_env`
+#[derive(Debug)]
+pub struct TelemetryConfig {
+    pub service_name: String,
+    pub service_version: String,
+    /// Overrides `deployment.environment` from `OTEL_RESOURCE_ATTRIBUTES`
+    deployment_environment: Option<String>,
+    protocol: OtlpProtocol,
+    endpoints: OtlpEndpoints,
+    signals: EnabledSignals,
+    /// Fail startup, rather than run without export, when the exporters can't
+    /// be built
+    required: bool,
+    prometheus_port: Option<u16>,
+    json_logs: bool,
+    metric_export_interval: Duration,
+    metric_export_timeout: Duration,
+    span_batch: BatchSettings,
+    log_batch: BatchSettings,
+    export_retry: ExportRetry,
+    latency_buckets_ms: Vec<f64>,
+    sampler: sdktrace::Sampler,
+}
+
+impl TelemetryConfig {
+    /// Invalid values for the OpenTelemetry spec variables are reported and
+    /// replaced by their defaults, as the spec asks; the service's own
+    /// variables are rejected
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let protocol = otlp_protocol();
+        let signals = EnabledSignals::from_env();
+        let deployment_environment = match env::var("DEPLOYMENT_ENVIRONMENT") {
+            Ok(value) if value.trim().is_empty() => {
+                return Err("DEPLOYMENT_ENVIRONMENT is set but empty".into())
+            }
+            Ok(value) => Some(value.trim().to_string()),
+            Err(_) => None,
+        };
+        // Our own setting, so unlike the spec variables a bad value is an error
+        let export_retries = match env::var("TELEMETRY_EXPORT_RETRIES") {
+            Err(_) => DEFAULT_EXPORT_RETRIES,
+            Ok(raw) => raw.trim().parse().map_err(|e| {
+                format!(
+                    "Invalid value for TELEMETRY_EXPORT_RETRIES: {:?} ({})",
+                    raw, e
+                )
+            })?,
+        };
+        let prometheus_port =
+            match env::var("PROMETHEUS_PORT") {
+                Err(_) => None,
+                Ok(_) if !signals.metrics => {
+                    eprintln!("[Service B] OTEL_METRICS_ENABLED=false, ignoring PROMETHEUS_PORT");
+                    None
+                }
+                Ok(port) => Some(port.parse().map_err(|e| {
+                    format!("Invalid value for PROMETHEUS_PORT: {:?} ({})", port, e)
+                })?),
+            };
+
+        Ok(Self {
+            service_name: env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "service-b".into()),
+            service_version: service_version(),
+            deployment_environment,
+            protocol,
+            endpoints: otlp_endpoints(protocol),
+            signals,
+            // A broken exporter configuration shouldn't keep the service from
+            // serving: unless TELEMETRY_REQUIRED=true, carry on with local
+            // logging only and providers that have nowhere to export to
+            required: env::var("TELEMETRY_REQUIRED")
+                .map(|v| v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            prometheus_port,
+            // LOG_FORMAT=json emits one flattened JSON object per line for log
+            // pipelines; anything else keeps the human-readable text format
+            json_logs: env::var("LOG_FORMAT")
+                .map(|format| format.eq_ignore_ascii_case("json"))
+                .unwrap_or(false),
+            metric_export_interval: metric_export_interval(),
+            metric_export_timeout: Duration::from_millis(positive_env(
+                "OTEL_METRIC_EXPORT_TIMEOUT",
+                DEFAULT_EXPORT_TIMEOUT_MS,
+            )),
+            span_batch: BatchSettings::from_env("OTEL_BSP", DEFAULT_SPAN_SCHEDULE_DELAY_MS),
+            log_batch: BatchSettings::from_env("OTEL_BLRP", DEFAULT_LOG_SCHEDULE_DELAY_MS),
+            export_retry: ExportRetry {
+                max_retries: export_retries,
+            },
+            latency_buckets_ms: latency_buckets_ms(),
+            sampler: traces_sampler(),
+        })
+    }
+}
+
+/// Queue size for the span and log batch processors when their `*_MAX_QUEUE_SIZE`
+/// is unset: four times the spec's 2048, so telemetry keeps queueing through
+/// a collector restart of a few seconds instead of being dropped
+const DEFAULT_MAX_QUEUE_SIZE: u64 = 8192;
+const DEFAULT_MAX_EXPORT_BATCH_SIZE: u64 = 512;
+const DEFAULT_SPAN_SCHEDULE_DELAY_MS: u64 = 5000;
+const DEFAULT_LOG_SCHEDULE_DELAY_MS: u64 = 1000;
+const DEFAULT_EXPORT_TIMEOUT_MS: u64 = 30_000;
+/// With the doubling backoff this rides out about 7s of failed exports
+const DEFAULT_EXPORT_RETRIES: u32 = 3;
+
+/// Batch processor settings for one signal, from the spec's `OTEL_BSP_*`
+/// (spans) or `OTEL_BLRP_*` (logs) variables
+#[derive(Clone, Copy, Debug)]
+struct BatchSettings {
+    max_queue_size: usize,
+    scheduled_delay: Duration,
+    max_export_batch_size: usize,
+    export_timeout: Duration,
+}
+
+impl BatchSettings {
+    fn from_env(prefix: &str, default_delay_ms: u64) -> Self {
+        let var = |name: &str| format!("{}_{}", prefix, name);
+        let max_queue_size = positive_env(&var("MAX_QUEUE_SIZE"), DEFAULT_MAX_QUEUE_SIZE);
+        let mut max_export_batch_size =
+            positive_env(&var("MAX_EXPORT_BATCH_SIZE"), DEFAULT_MAX_EXPORT_BATCH_SIZE);
+        if max_export_batch_size > max_queue_size {
+            eprintln!(
+                "[Service B] {} exceeds {}, using {}",
+                var("MAX_EXPORT_BATCH_SIZE"),
+                var("MAX_QUEUE_SIZE"),
+                max_queue_size
+            );
+            max_export_batch_size = max_queue_size;
+        }
+        Self {
+            max_queue_size: max_queue_size as usize,
+            scheduled_delay: Duration::from_millis(positive_env(
+                &var("SCHEDULE_DELAY"),
+                default_delay_ms,
+            )),
+            max_export_batch_size: max_export_batch_size as usize,
+            export_timeout: Duration::from_millis(positive_env(
+                &var("EXPORT_TIMEOUT"),
+                DEFAULT_EXPORT_TIMEOUT_MS,
+            )),
+        }
+    }
+
+    fn span_config(self) -> sdktrace::BatchConfig {
+        sdktrace::BatchConfigBuilder::default()
+            .with_max_queue_size(self.max_queue_size)
+            .with_scheduled_delay(self.scheduled_delay)
+            .with_max_export_batch_size(self.max_export_batch_size)
+            .with_max_export_timeout(self.export_timeout)
+            .build()
+    }
+
+    fn log_config(self) -> sdklogs::BatchConfig {
+        sdklogs::BatchConfigBuilder::default()
+            .with_max_queue_size(self.max_queue_size)
+            .with_scheduled_delay(self.scheduled_delay)
+            .with_max_export_batch_size(self.max_export_batch_size)
+            .with_max_export_timeout(self.export_timeout)
+            .build()
+    }
+}
+
+/// A positive integer from `var`, falling back to `default` when unset or
+/// invalid
+fn positive_env(var: &str, default: u64) -> u64 {
+    let Ok(raw) = env::var(var) else {
+        return default;
+    };
+    match raw.trim().parse::<u64>() {
+        Ok(value) if value > 0 => value,
+        _ => {
+            eprintln!(
+                "[Service B] Ignoring invalid {} {:?}, using {}",
+                var, raw, default
+            );
+            default
+        }
+    }
+}
+
+/// The deployed version: `SERVICE_VERSION` when set, otherwise the crate
+/// version the binary was built from
+fn service_version() -> String {
+    env::var("SERVICE_VERSION")
+        .ok()
+        .filter(|version| !version.is_empty())
+        .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string())
+}
+
+const DEPLOYMENT_ENVIRONMENT: Key = Key::from_static_str("deployment.environment");
+
+/// Resource attributes, lowest to highest precedence: built-in defaults, then
+/// auto-detected process/host/Kubernetes attributes, then
+/// `OTEL_RESOURCE_ATTRIBUTES`, then `DEPLOYMENT_ENVIRONMENT` and `service.name`.
+///
+/// `deployment.environment` therefore comes from `DEPLOYMENT_ENVIRONMENT`, else
+/// `OTEL_RESOURCE_ATTRIBUTES`, else defaults to `development`. Setting either
+/// to a blank value is rejected, since a missing environment tag is how alerts
+/// end up attributed to the wrong cluster.
+fn build_resource(config: &TelemetryConfig) -> Result<Resource, Box<dyn std::error::Error>> {
+    let defaults = Resource::new(vec![
+        KeyValue::new("service.version", config.service_version.clone()),
+        KeyValue::new(DEPLOYMENT_ENVIRONMENT, "development"),
+    ]);
+    let detected = Resource::from_detectors(
+        Duration::from_secs(1),
+        vec![
+            Box::new(ProcessResourceDetector),
+            Box::new(HostResourceDetector::default()),
+            Box::new(K8sResourceDetector),
+        ],
+    );
+    let from_env = EnvResourceDetector::new().detect(Duration::from_secs(1));
+    if from_env
+        .get(DEPLOYMENT_ENVIRONMENT)
+        .is_some_and(|value| value.as_str().trim().is_empty())
+    {
+        return Err("deployment.environment in OTEL_RESOURCE_ATTRIBUTES is empty".into());
+    }
+
+    let overrides = Resource::new(
+        config
+            .deployment_environment
+            .clone()
+            .map(|environment| KeyValue::new(DEPLOYMENT_ENVIRONMENT, environment))
+            .into_iter()
+            .chain([KeyValue::new("service.name", config.service_name.clone())]),
+    );
+
+    Ok(defaults.merge(&detected).merge(&from_env).merge(&overrides))
+}
+
+/// Pod, namespace and node names exposed through the downward API as
+/// `K8S_POD_NAME`, `K8S_NAMESPACE_NAME` and `K8S_NODE_NAME`; variables that
+/// aren't set (e.g. outside Kubernetes) are skipped
+struct K8sResourceDetector;
+
+impl ResourceDetector for K8sResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        Resource::new(
+            [
+                ("k8s.pod.name", "K8S_POD_NAME"),
+                ("k8s.namespace.name", "K8S_NAMESPACE_NAME"),
+                ("k8s.node.name", "K8S_NODE_NAME"),
+            ]
+            .into_iter()
+            .filter_map(|(key, var)| env::var(var).ok().map(|value| KeyValue::new(key, value))),
+        )
+    }
+}
+
+/// OTLP transport, from `OTEL_EXPORTER_OTLP_PROTOCOL`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OtlpProtocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+/// `grpc` (the default) or `http/protobuf`; anything else is reported and
+/// falls back to gRPC
+fn otlp_protocol() -> OtlpProtocol {
+    match env::var("OTEL_EXPORTER_OTLP_PROTOCOL").as_deref() {
+        Err(_) | Ok("grpc") => OtlpProtocol::Grpc,
+        Ok("http/protobuf") => OtlpProtocol::HttpProtobuf,
+        Ok(other) => {
+            eprintln!(
+                "[Service B] Unsupported OTEL_EXPORTER_OTLP_PROTOCOL {:?}, using grpc",
+                other
+            );
+            OtlpProtocol::Grpc
+        }
+    }
+}
+
+/// The OTLP endpoint for each signal
+#[derive(Debug)]
+struct OtlpEndpoints {
+    traces: String,
+    metrics: String,
+    logs: String,
+}
+
+/// Per-signal endpoints, as the OTLP spec resolves them: the signal's own
+/// `OTEL_EXPORTER_OTLP_<SIGNAL>_ENDPOINT` is used as given, falling back to
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`. Over HTTP the fallback has the signal path
+/// (`/v1/traces`, ...) appended; gRPC routes by service name and sends every
+/// signal to the base endpoint unchanged.
+fn otlp_endpoints(protocol: OtlpProtocol) -> OtlpEndpoints {
+    let base = env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok();
+    let resolve = |signal_var: &str, path: &str| {
+        env::var(signal_var).unwrap_or_else(|_| match protocol {
+            OtlpProtocol::Grpc => base
+                .clone()
+                .unwrap_or_else(|| "http://localhost:4317".into()),
+            OtlpProtocol::HttpProtobuf => format!(
+                "{}/{}",
+                base.as_deref()
+                    .unwrap_or("http://localhost:4318")
+                    .trim_end_matches('/'),
+                path
+            ),
+        })
+    };
+    OtlpEndpoints {
+        traces: resolve("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", "v1/traces"),
+        metrics: resolve("OTEL_EXPORTER_OTLP_METRICS_ENDPOINT", "v1/metrics"),
+        logs: resolve("OTEL_EXPORTER_OTLP_LOGS_ENDPOINT", "v1/logs"),
+    }
+}
+
+/// Which signals are exported, from `OTEL_TRACES_ENABLED`,
+/// `OTEL_METRICS_ENABLED` and `OTEL_LOGS_ENABLED`
+#[derive(Clone, Copy, Debug)]
+struct EnabledSignals {
+    traces: bool,
+    metrics: bool,
+    logs: bool,
+}
+
+impl EnabledSignals {
+    fn from_env() -> Self {
+        Self {
+            traces: signal_enabled("OTEL_TRACES_ENABLED"),
+            metrics: signal_enabled("OTEL_METRICS_ENABLED"),
+            logs: signal_enabled("OTEL_LOGS_ENABLED"),
+        }
+    }
+}
+
+/// On unless set to `false`; values other than `true`/`false` are reported and
+/// leave the signal on
+fn signal_enabled(var: &str) -> bool {
+    let Ok(raw) = env::var(var) else {
+        return true;
+    };
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "true" => true,
+        "false" => false,
+        _ => {
+            eprintln!(
+                "[Service B] Ignoring invalid {} {:?}, leaving it enabled",
+                var, raw
+            );
+            true
+        }
+    }
+}
+
+/// The OTLP exporter for each enabled signal
+#[derive(Default)]
+struct OtlpExporters {
+    span: Option<SpanExporter>,
+    log: Option<LogExporter>,
+    metric: Option<MetricExporter>,
+}
+
+fn build_otlp_exporters(
+    protocol: OtlpProtocol,
+    endpoints: &OtlpEndpoints,
+    signals: EnabledSignals,
+) -> Result<OtlpExporters, Box<dyn std::error::Error>> {
+    let exporters = match protocol {
+        OtlpProtocol::Grpc => OtlpExporters {
+            span: signals
+                .traces
+                .then(|| {
+                    SpanExporter::builder()
+                        .with_tonic()
+                        .with_endpoint(&endpoints.traces)
+                        .build()
+                })
+                .transpose()?,
+            log: signals
+                .logs
+                .then(|| {
+                    LogExporter::builder()
+                        .with_tonic()
+                        .with_endpoint(&endpoints.logs)
+                        .build()
+                })
+                .transpose()?,
+            metric: signals
+                .metrics
+                .then(|| {
+                    MetricExporter::builder()
+                        .with_tonic()
+                        .with_endpoint(&endpoints.metrics)
+                        .build()
+                })
+                .transpose()?,
+        },
+        OtlpProtocol::HttpProtobuf => OtlpExporters {
+            span: signals
+                .traces
+                .then(|| {
+                    SpanExporter::builder()
+                        .with_http()
+                        .with_protocol(Protocol::HttpBinary)
+                        .with_endpoint(&endpoints.traces)
+                        .build()
+                })
+                .transpose()?,
+            log: signals
+                .logs
+                .then(|| {
+                    LogExporter::builder()
+                        .with_http()
+                        .with_protocol(Protocol::HttpBinary)
+                        .with_endpoint(&endpoints.logs)
+                        .build()
+                })
+                .transpose()?,
+            metric: signals
+                .metrics
+                .then(|| {
+                    MetricExporter::builder()
+                        .with_http()
+                        .with_protocol(Protocol::HttpBinary)
+                        .with_endpoint(&endpoints.metrics)
+                        .build()
+                })
+                .transpose()?,
+        },
+    };
+    Ok(exporters)
+}
+
+pub fn init_telemetry(config: &Config) -> Result<TelemetryProviders, Box<dyn std::error::Error>> {
+    let config = &config.telemetry;
+    let signals = config.signals;
+
+    // W3C trace context and baggage for propagation across service boundaries
+    opentelemetry::global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+        Box::new(TraceContextPropagator::new()),
+        Box::new(BaggagePropagator::new()),
+    ]));
+
+    let resource = build_resource(config)?;
+    let identity = ServiceIdentity {
+        name: config.service_name.clone(),
+        version: resource
+            .get(Key::from_static_str("service.version"))
+            .map_or_else(
+                || config.service_version.clone(),
+                |version| version.to_string(),
+            ),
+    };
+    if let Some(environment) = resource.get(DEPLOYMENT_ENVIRONMENT) {
+        println!("[Service B] Deployment environment: {}", environment);
+    }
+
+    let exporters = match build_otlp_exporters(config.protocol, &config.endpoints, signals) {
+        Ok(exporters) => exporters,
+        Err(e) if config.required => {
+            return Err(format!("Failed to create OTLP exporters: {}", e).into())
+        }
+        Err(e) => {
+            eprintln!(
+                "[Service B] Failed to create OTLP exporters, continuing without telemetry export: {}",
+                e
+            );
+            OtlpExporters::default()
+        }
+    };
+
+    // Disabled signals get no provider at all, rather than one with nowhere to
+    // export to, so their work (e.g. creating spans) is skipped entirely
+    let tracer_provider = signals.traces.then(|| {
+        let mut builder = sdktrace::TracerProvider::builder()
+            .with_sampler(config.sampler.clone())
+            .with_resource(resource.clone());
+        if let Some(exporter) = exporters.span {
+            builder = builder.with_span_processor(
+                sdktrace::BatchSpanProcessor::builder(
+                    RetryingSpanExporter::new(exporter, config.export_retry),
+                    runtime::Tokio,
+                )
+                .with_batch_config(config.span_batch.span_config())
+                .build(),
+            );
+        }
+        builder.build()
+    });
+    let logger_provider = signals.logs.then(|| {
+        let mut builder = LoggerProvider::builder().with_resource(resource.clone());
+        if let Some(exporter) = exporters.log {
+            builder = builder.with_log_processor(
+                BatchLogProcessor::builder(
+                    RetryingLogExporter::new(exporter, config.export_retry),
+                    runtime::Tokio,
+                )
+                .with_batch_config(config.log_batch.log_config())
+                .build(),
+            );
+        }
+        builder.build()
+    });
+    let meter_provider = signals
+        .metrics
+        .then(|| build_meter_provider(config, resource, exporters.metric))
+        .transpose()?;
+
+    if let Some(meter_provider) = &meter_provider {
+        opentelemetry::global::set_meter_provider(meter_provider.clone());
+    }
+
+    // Create OpenTelemetry tracing layer
+    let otel_trace_layer = tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("service-b")));
+
+    // Create OpenTelemetry log bridge layer
+    let otel_log_layer = logger_provider
+        .as_ref()
+        .map(OpenTelemetryTracingBridge::new);
+
+    let (log_filter_layer, log_filter) = reload::Layer::new(EnvFilter::new("info"));
+
+    let (text_layer, json_layer) = if config.json_logs {
+        let json_layer = tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(FlattenedJson);
+        (None, Some(json_layer))
+    } else {
+        (Some(tracing_subscriber::fmt::layer()), None)
+    };
+
+    tracing_subscriber::registry()
+        .with(log_filter_layer)
+        .with(text_layer)
+        .with(json_layer)
+        .with(otel_trace_layer)
+        .with(otel_log_layer)
+        .init();
+
+    println!("[Service B] OpenTelemetry telemetry initialized");
+    for (signal, enabled, endpoint) in [
+        ("traces", signals.traces, &config.endpoints.traces),
+        ("metrics", signals.metrics, &config.endpoints.metrics),
+        ("logs", signals.logs, &config.endpoints.logs),
+    ] {
+        if enabled {
+            println!("[Service B] OTLP {} endpoint: {}", signal, endpoint);
+        } else {
+            println!("[Service B] OTLP {} export disabled", signal);
+        }
+    }
+    for (signal, enabled, batch) in [
+        ("traces", signals.traces, config.span_batch),
+        ("logs", signals.logs, config.log_batch),
+    ] {
+        if enabled {
+            println!(
+                "[Service B] OTLP {} batching: queue {}, batches of {}, every {}ms, {}ms timeout",
+                signal,
+                batch.max_queue_size,
+                batch.max_export_batch_size,
+                batch.scheduled_delay.as_millis(),
+                batch.export_timeout.as_millis()
+            );
+        }
+    }
+    if signals.metrics {
+        println!(
+            "[Service B] OTLP metrics export: every {}ms, {}ms timeout",
+            config.metric_export_interval.as_millis(),
+            config.metric_export_timeout.as_millis()
+        );
+    }
+    if signals.traces || signals.logs {
+        println!(
+            "[Service B] OTLP trace and log export retries: {}",
+            config.export_retry.max_retries
+        );
+    }
+
+    Ok(TelemetryProviders {
+        tracer_provider,
+        logger_provider,
+        meter_provider,
+        log_filter,
+        identity,
+    })
+}
+
+/// The meter provider with this service's histogram views, exporting over OTLP
+/// (when the exporter could be built) and optionally to Prometheus
+fn build_meter_provider(
+    config: &TelemetryConfig,
+    resource: Resource,
+    exporter: Option<MetricExporter>,
+) -> Result<SdkMeterProvider, Box<dyn std::error::Error>> {
+    let mut meter_provider_builder = SdkMeterProvider::builder().with_resource(resource);
+    if let Some(exporter) = exporter {
+        meter_provider_builder = meter_provider_builder.with_reader(
+            PeriodicReader::builder(exporter, runtime::Tokio)
+                .with_interval(config.metric_export_interval)
+                .with_timeout(config.metric_export_timeout)
+                .build(),
+        );
+    }
+
+    // Exemplars (trace/span ids attached to histogram samples) are not available:
+    // opentelemetry_sdk 0.27 has no exemplar reservoirs and always exports an
+    // empty exemplar list. Latency spikes are correlated with traces through the
+    // request/trace ids in the logs instead.
+    // Bucket boundaries tuned for this service's latencies, applied to every
+    // `*_duration_ms` histogram
+    let latency_view = new_view(
+        Instrument::new().name("*_duration_ms"),
+        Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: config.latency_buckets_ms.clone(),
+            record_min_max: true,
+        }),
+    )?;
+
+    // Powers of four from 64B to 4MiB (the default gRPC message size limit) for
+    // every `*_bytes` histogram
+    let size_view = new_view(
+        Instrument::new().name("*_bytes"),
+        Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: (0..9).map(|i| 64.0 * 4f64.powi(i)).collect(),
+            record_min_max: true,
+        }),
+    )?;
+
+    meter_provider_builder = meter_provider_builder
+        .with_view(latency_view)
+        .with_view(size_view);
+
+    // Optional Prometheus pull endpoint. It is a second reader on the same provider,
+    // so both exporters observe the same instruments without double counting.
+    if let Some(port) = config.prometheus_port {
+        let registry = prometheus::Registry::new();
+        let prometheus_exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .without_units()
+            .build()?;
+        meter_provider_builder = meter_provider_builder.with_reader(prometheus_exporter);
+        tokio::spawn(serve_prometheus(port, registry));
+    }
+
+    Ok(meter_provider_builder.build())
+}
+
+const DEFAULT_METRIC_EXPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// OTLP metric push interval from `OTEL_METRIC_EXPORT_INTERVAL_MS` (a positive
+/// number of milliseconds), falling back to 10s when unset or invalid
+fn metric_export_interval() -> Duration {
+    let Ok(raw) = env::var("OTEL_METRIC_EXPORT_INTERVAL_MS") else {
+        return DEFAULT_METRIC_EXPORT_INTERVAL;
+    };
+
+    match raw.trim().parse::<u64>() {
+        Ok(ms) if ms > 0 => Duration::from_millis(ms),
+        _ => {
+            eprintln!(
+                "[Service B] Ignoring invalid OTEL_METRIC_EXPORT_INTERVAL_MS {:?}, using {}ms",
+                raw,
+                DEFAULT_METRIC_EXPORT_INTERVAL.as_millis()
+            );
+            DEFAULT_METRIC_EXPORT_INTERVAL
+        }
+    }
+}
+
+const DEFAULT_LATENCY_BUCKETS_MS: [f64; 9] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 250.0, 500.0];
+
+/// Histogram boundaries from `LATENCY_BUCKETS_MS` (comma-separated, strictly
+/// increasing), falling back to the defaults when unset or invalid
+fn latency_buckets_ms() -> Vec<f64> {
+    let Ok(raw) = env::var("LATENCY_BUCKETS_MS") else {
+        return DEFAULT_LATENCY_BUCKETS_MS.to_vec();
+    };
+
+    let parsed: Result<Vec<f64>, _> = raw.split(',').map(|b| b.trim().parse::<f64>()).collect();
+    match parsed {
+        Ok(buckets) if !buckets.is_empty() && buckets.windows(2).all(|w| w[0] < w[1]) => buckets,
+        _ => {
+            eprintln!(
+                "[Service B] Ignoring invalid LATENCY_BUCKETS_MS {:?}, using defaults",
+                raw
+            );
+            DEFAULT_LATENCY_BUCKETS_MS.to_vec()
+        }
+    }
+}
+
+/// Sampler from the spec's `OTEL_TRACES_SAMPLER` / `OTEL_TRACES_SAMPLER_ARG`,
+/// defaulting to parent-based always-on. The ratio samplers take a probability
+/// in `[0, 1]` (default 1.0); unknown samplers and invalid ratios are reported
+/// and fall back to the defaults.
+fn traces_sampler() -> sdktrace::Sampler {
+    use sdktrace::Sampler;
+
+    let default = || Sampler::ParentBased(Box::new(Sampler::AlwaysOn));
+    let Ok(name) = env::var("OTEL_TRACES_SAMPLER") else {
+        return default();
+    };
+    let ratio = || match env::var("OTEL_TRACES_SAMPLER_ARG") {
+        Err(_) => 1.0,
+        Ok(arg) => match arg.trim().parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => ratio,
+            _ => {
+                eprintln!(
+                    "[Service B] Ignoring invalid OTEL_TRACES_SAMPLER_ARG {:?}, sampling all traces",
+                    arg
+                );
+                1.0
+            }
+        },
+    };
+
+    match name.trim() {
+        "always_on" => Sampler::AlwaysOn,
+        "always_off" => Sampler::AlwaysOff,
+        "traceidratio" => Sampler::TraceIdRatioBased(ratio()),
+        "parentbased_always_on" => default(),
+        "parentbased_always_off" => Sampler::ParentBased(Box::new(Sampler::AlwaysOff)),
+        "parentbased_traceidratio" => {
+            Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio())))
+        }
+        other => {
+            eprintln!(
+                "[Service B] Unknown OTEL_TRACES_SAMPLER {:?}, using parentbased_always_on",
+                other
+            );
+            default()
+        }
+    }
+}
+
+/// Serves the Prometheus text exposition format on `0.0.0.0:<port>/metrics`
+async fn serve_prometheus(port: u16, registry: prometheus::Registry) {
+    let app = axum::Router::new().route(
+        "/metrics",
+        axum::routing::get(move || {
+            let registry = registry.clone();
+            async move {
+                let mut buffer = Vec::new();
+                let encoder = prometheus::TextEncoder::new();
+                match encoder.encode(&registry.gather(), &mut buffer) {
+                    Ok(()) => (
+                        [(
+                            axum::http::header::CONTENT_TYPE,
+                            encoder.format_type().to_string(),
+                        )],
+                        buffer,
+                    )
+                        .into_response(),
+                    Err(e) => (
+                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to encode metrics: {}", e),
+                    )
+                        .into_response(),
+                }
+            }
+        }),
+    );
+
+    let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[Service B] Failed to bind Prometheus port {}: {}", port, e);
+            return;
+        }
+    };
+    println!(
+        "[Service B] Prometheus metrics available on port {}/metrics",
+        port
+    );
+    if let Err(e) = axum::serve(listener, app).await {
+        eprintln!("[Service B] Prometheus endpoint failed: {}", e);
+    }
+}
@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{BoxError, Layer, Service};
+use tracing::warn;
+
+use crate::{RequestStatus, ServiceBMetrics};
+
+/// Backstop on how long a handler may take to produce its response, whatever
+/// it is stuck on. Separate from (and normally longer than) the downstream call
+/// timeouts. Expired calls get `DEADLINE_EXCEEDED` and are recorded with the
+/// `timeout` status. For streaming responses only the time to the first
+/// message is bounded, since that's when the handler returns.
+#[derive(Clone)]
+pub struct HandlerTimeoutLayer {
+    timeout: Duration,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl HandlerTimeoutLayer {
+    pub fn new(timeout: Duration, metrics: Arc<ServiceBMetrics>) -> Self {
+        Self { timeout, metrics }
+    }
+}
+
+impl<S> Layer<S> for HandlerTimeoutLayer {
+    type Service = HandlerTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HandlerTimeout {
+            inner,
+            timeout: self.timeout,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Request extension set just before a timed-out handler is dropped, so the
+/// handler's drop guards can tell a timeout apart from the caller cancelling
+#[derive(Clone, Default)]
+pub struct HandlerTimedOut(Arc<AtomicBool>);
+
+impl HandlerTimedOut {
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+pub struct HandlerTimeout<S> {
+    inner: S,
+    timeout: Duration,
+    metrics: Arc<ServiceBMetrics>,
+}
+
+impl<S, B, ResBody> Service<http::Request<B>> for HandlerTimeout<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let timed_out = HandlerTimedOut::default();
+        req.extensions_mut().insert(timed_out.clone());
+        let method = req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let timeout = self.timeout;
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        let response = self.inner.call(req);
+
+        Box::pin(async move {
+            // Pinned here so the handler is only dropped once the flag is set
+            let mut response = pin!(tokio::time::timeout(timeout, response));
+            match response.as_mut().await {
+                Ok(result) => result.map_err(Into::into),
+                Err(_) => {
+                    timed_out.0.store(true, Ordering::Relaxed);
+                    warn!(
+                        "[Service B] {} exceeded the {}ms handler timeout",
+                        method,
+                        timeout.as_millis()
+                    );
+                    metrics.record_request(&method, RequestStatus::Timeout);
+                    metrics.record_latency(
+                        &method,
+                        RequestStatus::Timeout,
+                        start.elapsed().as_secs_f64() * 1000.0,
+                    );
+                    Err(Box::new(Status::deadline_exceeded(format!(
+                        "handler timed out after {}ms",
+                        timeout.as_millis()
+                    ))) as BoxError)
+                }
+            }
+        })
+    }
+}
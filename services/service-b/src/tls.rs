@@ -0,0 +1,55 @@
+use std::env;
+use std::error::Error;
+
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+fn read_pem(var: &str, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    std::fs::read(path).map_err(|e| format!("Failed to read {} ({}): {}", var, path, e).into())
+}
+
+/// Server-side TLS from `TLS_CERT_PATH`/`TLS_KEY_PATH`. Returns `None` (plaintext)
+/// when neither is set; setting only one of them is a configuration error.
+pub fn load_server_tls() -> Result<Option<ServerTlsConfig>, Box<dyn Error>> {
+    let cert_path = env::var("TLS_CERT_PATH").ok();
+    let key_path = env::var("TLS_KEY_PATH").ok();
+
+    match (cert_path, key_path) {
+        (None, None) => Ok(None),
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_pem("TLS_CERT_PATH", &cert_path)?;
+            let key = read_pem("TLS_KEY_PATH", &key_path)?;
+            Ok(Some(
+                ServerTlsConfig::new().identity(Identity::from_pem(cert, key)),
+            ))
+        }
+        _ => Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".into()),
+    }
+}
+
+/// Client-side (mutual) TLS for downstream channels from `CA_CERT_PATH`,
+/// `CLIENT_CERT_PATH` and `CLIENT_KEY_PATH`. Returns `None` (plaintext) when none
+/// are set. The client identity is optional, but cert and key must come as a pair.
+pub fn load_client_tls() -> Result<Option<ClientTlsConfig>, Box<dyn Error>> {
+    let ca_path = env::var("CA_CERT_PATH").ok();
+    let cert_path = env::var("CLIENT_CERT_PATH").ok();
+    let key_path = env::var("CLIENT_KEY_PATH").ok();
+
+    if ca_path.is_none() && cert_path.is_none() && key_path.is_none() {
+        return Ok(None);
+    }
+
+    let mut config = ClientTlsConfig::new();
+    if let Some(ca_path) = ca_path {
+        config = config.ca_certificate(Certificate::from_pem(read_pem("CA_CERT_PATH", &ca_path)?));
+    }
+    match (cert_path, key_path) {
+        (None, None) => {}
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_pem("CLIENT_CERT_PATH", &cert_path)?;
+            let key = read_pem("CLIENT_KEY_PATH", &key_path)?;
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+        _ => return Err("CLIENT_CERT_PATH and CLIENT_KEY_PATH must be set together".into()),
+    }
+    Ok(Some(config))
+}
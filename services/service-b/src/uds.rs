@@ -0,0 +1,64 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use hyper_util::rt::TokioIo;
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+
+use crate::ChannelTuning;
+
+const UNIX_SCHEME: &str = "unix://";
+
+/// The socket path of a `unix:///path/to.sock` target, or `None` for `host:port`
+pub fn unix_path(addr: &str) -> Option<&str> {
+    addr.trim().strip_prefix(UNIX_SCHEME)
+}
+
+/// Lazily connected channel to a downstream listening on a Unix socket.
+/// Connections are plaintext: the socket's file permissions are the access
+/// control, so `CA_CERT_PATH` and friends don't apply.
+pub fn unix_channel(
+    path: &str,
+    tuning: ChannelTuning,
+) -> Result<Channel, Box<dyn std::error::Error>> {
+    // The URI is only used for the :authority header; the connector ignores it
+    let endpoint = tuning.apply(Endpoint::try_from("http://localhost")?);
+
+    let path = PathBuf::from(path);
+    let connector = service_fn(move |_: Uri| {
+        let path = path.clone();
+        async move { Ok::<_, io::Error>(TokioIo::new(UnixStream::connect(path).await?)) }
+    });
+    Ok(endpoint.connect_with_connector_lazy(connector))
+}
+
+/// Listens on `path`, replacing a socket file left behind by an unclean exit
+pub fn bind(path: &Path) -> io::Result<(UnixListenerStream, SocketFile)> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(path)?;
+    Ok((
+        UnixListenerStream::new(listener),
+        SocketFile(path.to_path_buf()),
+    ))
+}
+
+/// Removes the socket file when dropped, so a restart doesn't trip over it
+pub struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            eprintln!(
+                "[Service B] Failed to remove socket {}: {}",
+                self.0.display(),
+                e
+            );
+        }
+    }
+}
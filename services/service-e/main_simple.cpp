@@ -6,6 +6,7 @@
 #include <chrono>
 #include <cstdlib>
 #include <numeric>
+#include <algorithm>
 
 #include <grpcpp/grpcpp.h>
 #include <grpcpp/health_check_service_interface.h>
@@ -50,6 +51,11 @@ public:
                                              request->input_values().end(), 0.0);
                 results.push_back(sum / request->input_values_size());
             }
+        } else if (operation == "max") {
+            if (request->input_values_size() > 0) {
+                results.push_back(*std::max_element(request->input_values().begin(),
+                                                    request->input_values().end()));
+            }
         } else if (operation == "transform") {
             for (const auto& val : request->input_values()) {
                 results.push_back(val * 2.0 + 1.0);